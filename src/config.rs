@@ -0,0 +1,104 @@
+use std::env;
+use std::str::FromStr;
+
+use log::warn;
+
+use crate::hash::HashAlgorithm;
+use crate::service::data_store::DataStoreKind;
+
+/// Server settings, read from the environment at startup
+#[derive(Clone, Debug)]
+pub(crate) struct Config {
+    /// How long the tombstones of deleted entries are kept, in days (ROADWORK_TOMBSTONE_RETENTION_DAYS)
+    pub(crate) tombstone_retention_days: u64,
+    /// How many snapshots are kept for each dataset, 0 keeps them all (ROADWORK_SNAPSHOT_MAX_COUNT)
+    pub(crate) snapshot_max_count: u64,
+    /// How long the snapshots are kept, in days (ROADWORK_SNAPSHOT_MAX_AGE_DAYS)
+    pub(crate) snapshot_max_age_days: u64,
+    /// Where the dataset entries are stored: sqlite, filesystem or memory (ROADWORK_DATA_STORE)
+    pub(crate) data_store: DataStoreKind,
+    /// The directory of the filesystem data store (ROADWORK_DATA_DIR)
+    pub(crate) data_dir: String,
+    /// How many times an event is sent to a failing webhook (ROADWORK_WEBHOOK_MAX_ATTEMPTS)
+    pub(crate) webhook_max_attempts: u32,
+    /// The delay before the first retry, doubled after each retry (ROADWORK_WEBHOOK_RETRY_DELAY_SECONDS)
+    pub(crate) webhook_retry_delay_seconds: u64,
+    /// How often the opendata feeds are pulled, 0 disables the pull (ROADWORK_FEED_INTERVAL_MINUTES)
+    pub(crate) feed_interval_minutes: u64,
+    /// The only directory the local opendata feeds are read from (ROADWORK_FEED_DIR)
+    pub(crate) feed_dir: String,
+    /// How long an access token from /user/login is accepted (ROADWORK_TOKEN_TTL_MINUTES)
+    pub(crate) token_ttl_minutes: u64,
+    /// How long a refresh token can be exchanged for a new access token (ROADWORK_REFRESH_TOKEN_TTL_DAYS)
+    pub(crate) refresh_token_ttl_days: u64,
+    /// How many failed password attempts lock out a username (ROADWORK_LOCKOUT_THRESHOLD)
+    pub(crate) lockout_threshold: u32,
+    /// How many failed password attempts lock out a client address (ROADWORK_CLIENT_LOCKOUT_THRESHOLD)
+    pub(crate) client_lockout_threshold: u32,
+    /// How long a lockout lasts, in minutes (ROADWORK_LOCKOUT_MINUTES)
+    pub(crate) lockout_minutes: u64,
+    /// The delay a username waits after its first failed password attempt, doubled after each failure (ROADWORK_LOGIN_BACKOFF_SECONDS)
+    pub(crate) login_backoff_seconds: u64,
+    /// The algorithm of the new password hashes: argon2id or bcrypt (ROADWORK_PASSWORD_HASH)
+    pub(crate) password_hash_algorithm: HashAlgorithm,
+    /// The cost of the bcrypt password hashes, from 4 to 31 (ROADWORK_BCRYPT_COST)
+    pub(crate) bcrypt_cost: u32,
+}
+
+impl Config {
+    pub(crate) fn from_env() -> Self {
+        Config {
+            tombstone_retention_days: env_or("ROADWORK_TOMBSTONE_RETENTION_DAYS", 30),
+            snapshot_max_count: env_or("ROADWORK_SNAPSHOT_MAX_COUNT", 20),
+            snapshot_max_age_days: env_or("ROADWORK_SNAPSHOT_MAX_AGE_DAYS", 30),
+            data_store: env_or("ROADWORK_DATA_STORE", DataStoreKind::Sqlite),
+            data_dir: env_or("ROADWORK_DATA_DIR", "data".to_string()),
+            webhook_max_attempts: env_or("ROADWORK_WEBHOOK_MAX_ATTEMPTS", 5),
+            webhook_retry_delay_seconds: env_or("ROADWORK_WEBHOOK_RETRY_DELAY_SECONDS", 10),
+            feed_interval_minutes: env_or("ROADWORK_FEED_INTERVAL_MINUTES", 60),
+            feed_dir: env_or("ROADWORK_FEED_DIR", "feeds".to_string()),
+            token_ttl_minutes: env_or("ROADWORK_TOKEN_TTL_MINUTES", 60),
+            refresh_token_ttl_days: env_or("ROADWORK_REFRESH_TOKEN_TTL_DAYS", 30),
+            lockout_threshold: env_or("ROADWORK_LOCKOUT_THRESHOLD", 5),
+            client_lockout_threshold: env_or("ROADWORK_CLIENT_LOCKOUT_THRESHOLD", 20),
+            lockout_minutes: env_or("ROADWORK_LOCKOUT_MINUTES", 15),
+            login_backoff_seconds: env_or("ROADWORK_LOGIN_BACKOFF_SECONDS", 1),
+            password_hash_algorithm: env_or("ROADWORK_PASSWORD_HASH", HashAlgorithm::Argon2id),
+            bcrypt_cost: bcrypt_cost(env_or("ROADWORK_BCRYPT_COST", bcrypt::DEFAULT_COST)),
+        }
+    }
+
+    /// Everything in memory and a data directory of its own, the tests never touch the working directory
+    #[cfg(test)]
+    pub(crate) fn for_tests() -> Self {
+        let data_dir = env::temp_dir().join(format!("roadwork-test-{}", rand::random::<u64>()));
+        Config {
+            data_store: DataStoreKind::Memory,
+            data_dir: data_dir.to_string_lossy().to_string(),
+            ..Config::from_env()
+        }
+    }
+}
+
+fn bcrypt_cost(cost: u32) -> u32 {
+    if (4..=31).contains(&cost) {
+        cost
+    } else {
+        warn!(
+            "Invalid bcrypt cost {}, using {}",
+            cost,
+            bcrypt::DEFAULT_COST
+        );
+        bcrypt::DEFAULT_COST
+    }
+}
+
+fn env_or<T: FromStr>(name: &str, default: T) -> T {
+    match env::var(name) {
+        Ok(value) => value.parse().unwrap_or_else(|_| {
+            warn!("Invalid value {} for {}, using default", value, name);
+            default
+        }),
+        Err(_) => default,
+    }
+}
@@ -1,9 +1,36 @@
 use thiserror::Error;
 
 #[derive(Debug, Error)]
+#[allow(clippy::enum_variant_names)]
 pub(crate) enum Error {
     #[error("SqlxError {0}")]
     SqlxError(#[from] sqlx::Error),
     #[error("InvalidSql")]
     NetworkError(#[from] std::io::Error),
-}
\ No newline at end of file
+    #[error("InitError {0}")]
+    InitError(String),
+    #[error("HttpClientError {0}")]
+    HttpClientError(#[from] reqwest::Error),
+}
+
+/// An error of the data service
+#[derive(Debug, Error)]
+pub(crate) enum DataError {
+    /// The dataset is quarantined and refuses the syncs until an administrator repairs it
+    #[error("Dataset {0}/{1} is quarantined")]
+    Quarantined(String, String),
+    #[error("{0}")]
+    Storage(String),
+}
+
+impl From<String> for DataError {
+    fn from(message: String) -> Self {
+        DataError::Storage(message)
+    }
+}
+
+impl From<DataError> for String {
+    fn from(err: DataError) -> Self {
+        err.to_string()
+    }
+}
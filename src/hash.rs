@@ -1,13 +1,120 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::{Argon2, Params};
+use bcrypt::HashParts;
 use log::warn;
+use serde::Serialize;
+
+use crate::config::Config;
+
+/// The bcrypt version produced by salt, the hashes of the other versions are rehashed
+const BCRYPT_VERSION_PREFIX: &str = "$2b$";
+const ARGON2ID_PREFIX: &str = "$argon2id$";
+
+/// The algorithms of the password hashes stored in user.password_hash
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum HashAlgorithm {
+    Bcrypt,
+    Argon2id,
+    /// Neither bcrypt nor Argon2id, the password cannot be checked
+    Unknown,
+}
+
+impl HashAlgorithm {
+    pub(crate) fn of(password_hash: &str) -> Self {
+        if password_hash.starts_with("$2") {
+            HashAlgorithm::Bcrypt
+        } else if password_hash.starts_with(ARGON2ID_PREFIX) {
+            HashAlgorithm::Argon2id
+        } else {
+            HashAlgorithm::Unknown
+        }
+    }
+}
 
-pub(crate) fn salt(password: &String) -> String {
-    bcrypt::hash(password, 4).unwrap()
+impl Display for HashAlgorithm {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HashAlgorithm::Bcrypt => write!(f, "bcrypt"),
+            HashAlgorithm::Argon2id => write!(f, "argon2id"),
+            HashAlgorithm::Unknown => write!(f, "unknown"),
+        }
+    }
 }
 
+impl FromStr for HashAlgorithm {
+    type Err = String;
+
+    /// The algorithms salt can produce
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bcrypt" => Ok(HashAlgorithm::Bcrypt),
+            "argon2id" => Ok(HashAlgorithm::Argon2id),
+            _ => Err(format!("Unknown password hash algorithm {}", s)),
+        }
+    }
+}
+
+/// Hash a password with the algorithm of the configuration
+pub(crate) fn salt(password: &String, config: &Config) -> String {
+    match config.password_hash_algorithm {
+        HashAlgorithm::Bcrypt => bcrypt::hash(password, config.bcrypt_cost).unwrap(),
+        _ => {
+            let salt = SaltString::encode_b64(&rand::random::<[u8; 16]>()).unwrap();
+            Argon2::default()
+                .hash_password(password.as_bytes(), &salt)
+                .unwrap()
+                .to_string()
+        }
+    }
+}
+
+/// Check a password against a bcrypt or an Argon2id hash
 pub(crate) fn check(expected_password_hash: &str, password: &str) -> bool {
-    let result = bcrypt::verify(password, expected_password_hash);
+    let result = match HashAlgorithm::of(expected_password_hash) {
+        HashAlgorithm::Bcrypt => {
+            bcrypt::verify(password, expected_password_hash).map_err(|err| err.to_string())
+        }
+        HashAlgorithm::Argon2id => PasswordHash::new(expected_password_hash)
+            .map(|password_hash| {
+                Argon2::default()
+                    .verify_password(password.as_bytes(), &password_hash)
+                    .is_ok()
+            })
+            .map_err(|err| err.to_string()),
+        HashAlgorithm::Unknown => Err("unknown hash algorithm".to_string()),
+    };
     result.unwrap_or_else(|err| {
         warn!("Error checking password: {}", err);
         false
     })
 }
+
+/// A hash of another algorithm than the configured one,
+/// or weaker than the one salt would produce with the configuration
+pub(crate) fn needs_rehash(password_hash: &str, config: &Config) -> bool {
+    if HashAlgorithm::of(password_hash) != config.password_hash_algorithm {
+        return true;
+    }
+    match config.password_hash_algorithm {
+        HashAlgorithm::Bcrypt => {
+            !password_hash.starts_with(BCRYPT_VERSION_PREFIX)
+                || password_hash
+                    .parse::<HashParts>()
+                    .map_or(true, |hash_parts| {
+                        hash_parts.get_cost() < config.bcrypt_cost
+                    })
+        }
+        _ => PasswordHash::new(password_hash)
+            .ok()
+            .and_then(|password_hash| Params::try_from(&password_hash).ok())
+            .is_none_or(|params| {
+                params.m_cost() < Params::DEFAULT_M_COST
+                    || params.t_cost() < Params::DEFAULT_T_COST
+                    || params.p_cost() < Params::DEFAULT_P_COST
+            }),
+    }
+}
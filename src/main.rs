@@ -1,14 +1,27 @@
+use std::net::SocketAddr;
+
 use axum::http::StatusCode;
 use axum::routing::get;
 use axum::Router;
 use log::info;
+use tokio::sync::mpsc;
 use crate::error::Error;
 use crate::router::admin::admin_routes;
 use crate::router::roadwork::roadwork_routes;
+use crate::router::team::team_routes;
 use crate::router::user::user_routes;
+use crate::config::Config;
+use crate::service::data::DataService;
+use crate::service::data_repository::DataRepository;
+use crate::service::feed;
+use crate::service::feed::FeedService;
+use crate::service::housekeeping;
 use crate::service::user::AdminService;
 use crate::service::user_repository::UserRepository;
+use crate::service::webhook;
+use crate::service::webhook::WebhookService;
 
+mod config;
 mod hash;
 mod router;
 mod service;
@@ -18,17 +31,41 @@ mod error;
 pub(crate) struct RoadworkServerData {
     user_repository: UserRepository,
     admin_service: AdminService,
+    data_service: DataService,
+    webhook_service: WebhookService,
+    feed_service: FeedService,
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
     env_logger::init();
     info!("Starting Roadwork server");
-    let user_repository = UserRepository::new().await?;
-    let admin_service = AdminService::new(user_repository.clone()).await;
+    let config = Config::from_env();
+    let user_repository = UserRepository::new(&config).await?;
+    let admin_service = AdminService::new(user_repository.clone(), config.clone()).await;
+    let data_repository = DataRepository::new(user_repository.pool(), &config).await?;
+    let webhook_service = WebhookService::new(
+        data_repository.clone(),
+        user_repository.clone(),
+        config.clone(),
+    )?;
+    let (webhook_changes, webhook_changes_receiver) = mpsc::unbounded_channel();
+    let data_service = DataService::new(data_repository.clone(), config.clone(), webhook_changes);
+    let feed_service = FeedService::new(
+        data_repository.clone(),
+        data_service.clone(),
+        user_repository.clone(),
+        config.clone(),
+    )?;
+    webhook::start(webhook_service.clone(), webhook_changes_receiver);
+    feed::start(feed_service.clone(), config.clone());
+    housekeeping::start(data_service.clone(), config);
     let roadwork_server_data = RoadworkServerData {
         user_repository,
         admin_service,
+        data_service,
+        webhook_service,
+        feed_service,
     };
     let app = Router::new()
         .route("/info", get(|| async { "Roadwork server by kpouer" }))
@@ -36,11 +73,16 @@ async fn main() -> Result<(), Error> {
         .nest("/admin", admin_routes())
         .nest("/user", user_routes())
         .nest("/roadwork", roadwork_routes())
+        .nest("/team", team_routes())
         .with_state(roadwork_server_data)
         .fallback(|| async { (StatusCode::NOT_FOUND, "Not Found") });
     let addr = "0.0.0.0:8080";
     let listener = tokio::net::TcpListener::bind(addr).await?;
     info!("Listen on {addr}");
-    axum::serve(listener, app).await?;
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await?;
     Ok(())
 }
@@ -1,11 +1,24 @@
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
+
 use axum::extract::{Path, State};
 use axum::http::StatusCode;
 use axum::routing::{delete, get, post};
 use axum::{Json, Router};
-use axum_auth::AuthBasic;
 use log::{info, warn};
 use roadwork_sync_lib::user::User;
-use crate::service::user::AdminService;
+use serde::Serialize;
+use crate::hash::HashAlgorithm;
+use crate::service::data::merge_strategy::merge_strategy_names;
+use crate::service::data::opendata_service::is_valid_opendata_service_name;
+use crate::service::data::{SnapshotDiff, ALL_SERVICES, MAX_RETENTION_DAYS};
+use crate::service::data_repository::{
+    OpendataFeed, OpendataService, QuarantinedDataset, QuarantinedEntry, RetentionPolicy, SnapshotInfo, StaleEntry, Webhook,
+    WebhookDelivery,
+};
+use crate::service::feed::FeedReport;
+use crate::service::throttle::Lockout;
+use crate::service::user::{AdminService, Credentials};
 use crate::RoadworkServerData;
 
 pub(crate) fn admin_routes() -> Router<RoadworkServerData> {
@@ -18,26 +31,60 @@ pub(crate) fn admin_routes() -> Router<RoadworkServerData> {
         .route("/user/{user_name}", delete(delete_user))
         .route("/user/{user_name}/new_password", post(new_password))
         .route("/link/user/{user_name}/team/{team_name}", get(link_user_team))
+        .route("/password_hashes", get(count_password_hashes))
+        .route("/lockouts", get(list_lockouts))
+        .route("/lockout/user/{user_name}", delete(unlock_user))
+        .route("/lockout/client/{client}", delete(unlock_client))
+        .route("/team/{team_name}/admins", get(list_team_admins))
+        .route("/team/{team_name}/admin/{user_name}", post(add_team_admin))
+        .route("/team/{team_name}/admin/{user_name}", delete(delete_team_admin))
+        .route("/retention", get(list_retention_policies))
+        .route("/retention/dry_run", get(retention_dry_run))
+        .route("/retention/{team_name}/{opendata_service}", post(set_retention_policy))
+        .route("/retention/{team_name}/{opendata_service}", delete(delete_retention_policy))
+        .route("/snapshots/{team_name}/{opendata_service}", get(list_snapshots))
+        .route("/snapshots/{team_name}/{opendata_service}/diff/{from}/{to}", get(diff_snapshots))
+        .route("/snapshots/{team_name}/{opendata_service}/{snapshot_id}/restore", post(restore_snapshot))
+        .route("/opendata_services", get(list_opendata_services))
+        .route("/opendata_service", post(save_opendata_service))
+        .route("/opendata_service/{opendata_service}", delete(delete_opendata_service))
+        .route("/merge_strategies", get(list_merge_strategies))
+        .route("/merge_strategy/{opendata_service}", post(set_merge_strategy))
+        .route("/merge_strategy/{opendata_service}", delete(delete_merge_strategy))
+        .route("/quarantine", get(list_quarantined))
+        .route("/quarantine/{team_name}/{opendata_service}/repair", post(repair_quarantined))
+        .route("/quarantine/{team_name}/{opendata_service}/restore", post(restore_quarantined))
+        .route("/quarantine/{team_name}/{opendata_service}/entries", get(list_quarantined_entries))
+        .route("/quarantine/{team_name}/{opendata_service}/entries", delete(purge_quarantined_entries))
+        .route("/webhooks", get(list_webhooks))
+        .route("/webhook", post(add_webhook))
+        .route("/webhook/{webhook_id}", delete(delete_webhook))
+        .route("/webhook/{webhook_id}/deliveries", get(list_webhook_deliveries))
+        .route("/webhook/{webhook_id}/ping", post(ping_webhook))
+        .route("/feeds", get(list_feeds))
+        .route("/feed", post(save_feed))
+        .route("/feed/{opendata_service}", delete(delete_feed))
+        .route("/feed/{opendata_service}/run", post(run_feed))
 }
 
 async fn list_teams(
-    AuthBasic((username, password)): AuthBasic,
+    credentials: Credentials,
     State(state): State<RoadworkServerData>,
 ) -> Result<Json<Vec<String>>, StatusCode> {
     info!("list_teams");
-    check_admin(&state.admin_service, &username, &password).await?;
+    check_admin(&state.admin_service, &credentials).await?;
     let teams = state.user_repository.list_teams().await;
     info!("list_teams -> {:?}", teams);
     Ok(Json(teams))
 }
 
 async fn add_team(
-    AuthBasic((username, password)): AuthBasic,
+    credentials: Credentials,
     State(state): State<RoadworkServerData>,
     Path(team_name): Path<String>,
 ) -> Result<&'static str, StatusCode> {
     info!("add_team {}", team_name);
-    check_admin(&state.admin_service, &username, &password).await?;
+    check_admin(&state.admin_service, &credentials).await?;
     state
         .user_repository
         .insert_team(team_name.as_str())
@@ -46,12 +93,12 @@ async fn add_team(
 }
 
 async fn delete_team(
-    AuthBasic((username, password)): AuthBasic,
+    credentials: Credentials,
     State(state): State<RoadworkServerData>,
     Path(removed_team): Path<String>,
 ) -> Result<&'static str, StatusCode> {
     info!("delete_team {}", removed_team);
-    check_admin(&state.admin_service, &username, &password).await?;
+    check_admin(&state.admin_service, &credentials).await?;
     state
         .user_repository
         .delete_team(&removed_team)
@@ -60,23 +107,23 @@ async fn delete_team(
 }
 
 async fn list_users(
-    AuthBasic((username, password)): AuthBasic,
+    credentials: Credentials,
     State(state): State<RoadworkServerData>,
 ) -> Result<Json<Vec<String>>, StatusCode> {
     info!("list_users");
-    check_admin(&state.admin_service, &username, &password).await?;
+    check_admin(&state.admin_service, &credentials).await?;
     let user_names = state.user_repository.list_users().await;
     info!("list_users -> {:?}", user_names);
     Ok(Json(user_names))
 }
 
 async fn link_user_team(
-    AuthBasic((username, password)): AuthBasic,
+    credentials: Credentials,
     State(state): State<RoadworkServerData>,
     Path((user_name, team_name)): Path<(String, String)>,
 ) -> Result<&'static str, StatusCode> {
     info!("link_user_team {} {}", user_name, team_name);
-    check_admin(&state.admin_service, &username, &password).await?;
+    check_admin(&state.admin_service, &credentials).await?;
     state
         .user_repository
         .link_user_team(&user_name, &team_name)
@@ -84,13 +131,116 @@ async fn link_user_team(
         .map_or(Ok("KO"), |_| Ok("OK"))
 }
 
+/// How many users have a password hash of each algorithm, to follow the migration to Argon2id
+async fn count_password_hashes(
+    credentials: Credentials,
+    State(state): State<RoadworkServerData>,
+) -> Result<Json<BTreeMap<HashAlgorithm, usize>>, StatusCode> {
+    info!("count_password_hashes");
+    check_admin(&state.admin_service, &credentials).await?;
+    state
+        .admin_service
+        .count_password_hashes()
+        .await
+        .map(Json)
+        .map_err(|err| {
+            warn!("count_password_hashes failed: {}", err);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+/// The usernames and client addresses throttled after failed password attempts
+async fn list_lockouts(
+    credentials: Credentials,
+    State(state): State<RoadworkServerData>,
+) -> Result<Json<Vec<Lockout>>, StatusCode> {
+    info!("list_lockouts");
+    check_admin(&state.admin_service, &credentials).await?;
+    Ok(Json(state.admin_service.list_lockouts()))
+}
+
+async fn unlock_user(
+    credentials: Credentials,
+    State(state): State<RoadworkServerData>,
+    Path(user_name): Path<String>,
+) -> Result<&'static str, StatusCode> {
+    info!("unlock_user {}", user_name);
+    check_admin(&state.admin_service, &credentials).await?;
+    if state.admin_service.unlock_user(&user_name) {
+        Ok("OK")
+    } else {
+        Ok("KO")
+    }
+}
+
+async fn unlock_client(
+    credentials: Credentials,
+    State(state): State<RoadworkServerData>,
+    Path(client): Path<IpAddr>,
+) -> Result<&'static str, StatusCode> {
+    info!("unlock_client {}", client);
+    check_admin(&state.admin_service, &credentials).await?;
+    if state.admin_service.unlock_client(client) {
+        Ok("OK")
+    } else {
+        Ok("KO")
+    }
+}
+
+async fn list_team_admins(
+    credentials: Credentials,
+    State(state): State<RoadworkServerData>,
+    Path(team_name): Path<String>,
+) -> Result<Json<Vec<String>>, StatusCode> {
+    info!("list_team_admins {}", team_name);
+    check_admin(&state.admin_service, &credentials).await?;
+    state
+        .user_repository
+        .list_team_admins(&team_name)
+        .await
+        .map(Json)
+        .map_err(|err| {
+            warn!("list_team_admins failed: {}", err);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+/// Let a member of the team manage the api keys of the team
+async fn add_team_admin(
+    credentials: Credentials,
+    State(state): State<RoadworkServerData>,
+    Path((team_name, user_name)): Path<(String, String)>,
+) -> Result<&'static str, StatusCode> {
+    info!("add_team_admin {} {}", team_name, user_name);
+    check_admin(&state.admin_service, &credentials).await?;
+    state
+        .user_repository
+        .insert_team_admin(&user_name, &team_name)
+        .await
+        .map_or(Ok("KO"), |_| Ok("OK"))
+}
+
+async fn delete_team_admin(
+    credentials: Credentials,
+    State(state): State<RoadworkServerData>,
+    Path((team_name, user_name)): Path<(String, String)>,
+) -> Result<&'static str, StatusCode> {
+    info!("delete_team_admin {} {}", team_name, user_name);
+    check_admin(&state.admin_service, &credentials).await?;
+    state
+        .user_repository
+        .delete_team_admin(&user_name, &team_name)
+        .await
+        .map_or(Ok("KO"), |_| Ok("OK"))
+}
+
 async fn add_user(
-    AuthBasic((username, password)): AuthBasic,
+    credentials: Credentials,
     State(state): State<RoadworkServerData>,
     Json(new_user): Json<User>,
 ) -> Result<&'static str, StatusCode> {
     info!("add_user {:?}", new_user);
-    check_admin(&state.admin_service, &username, &password).await?;
+    check_admin(&state.admin_service, &credentials).await?;
     state
         .user_repository
         .insert_user(&new_user)
@@ -99,13 +249,13 @@ async fn add_user(
 }
 
 async fn new_password(
-    AuthBasic((username, password)): AuthBasic,
+    credentials: Credentials,
     State(state): State<RoadworkServerData>,
     Path(user_name): Path<String>,
     new_password: String,
 ) -> Result<&'static str, StatusCode> {
     info!("new_password for user {}", user_name);
-    check_admin(&state.admin_service, &username, &password).await?;
+    check_admin(&state.admin_service, &credentials).await?;
     state
         .admin_service
         .change_password(&user_name, &new_password)
@@ -114,12 +264,12 @@ async fn new_password(
 }
 
 async fn delete_user(
-    AuthBasic((username, password)): AuthBasic,
+    credentials: Credentials,
     State(state): State<RoadworkServerData>,
     Path(removed_user): Path<String>,
 ) -> Result<&'static str, StatusCode> {
     info!("remove_user {}", removed_user);
-    check_admin(&state.admin_service, &username, &password).await?;
+    check_admin(&state.admin_service, &credentials).await?;
     state
         .user_repository
         .delete_user(&removed_user)
@@ -127,16 +277,599 @@ async fn delete_user(
         .map_or(Ok("KO"), |_| Ok("OK"))
 }
 
+async fn list_retention_policies(
+    credentials: Credentials,
+    State(state): State<RoadworkServerData>,
+) -> Result<Json<Vec<RetentionPolicy>>, StatusCode> {
+    info!("list_retention_policies");
+    check_admin(&state.admin_service, &credentials).await?;
+    state
+        .data_service
+        .list_retention_policies()
+        .await
+        .map(Json)
+        .map_err(|err| {
+            warn!("list_retention_policies failed: {}", err);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+/// Set the retention in days of a team dataset, the service "*" applies to all the services of the team
+async fn set_retention_policy(
+    credentials: Credentials,
+    State(state): State<RoadworkServerData>,
+    Path((team_name, opendata_service)): Path<(String, String)>,
+    retention_days: String,
+) -> Result<&'static str, StatusCode> {
+    info!(
+        "set_retention_policy {} {} {}",
+        team_name, opendata_service, retention_days
+    );
+    check_admin(&state.admin_service, &credentials).await?;
+    if opendata_service == ALL_SERVICES {
+        check_team(&state, &team_name).await?;
+    } else {
+        check_dataset(&state, &team_name, &opendata_service).await?;
+    }
+    let retention_days: u64 = retention_days
+        .trim()
+        .parse()
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+    if retention_days > MAX_RETENTION_DAYS {
+        warn!("Retention of {} days is too long", retention_days);
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    state
+        .data_service
+        .set_retention_policy(&team_name, &opendata_service, retention_days)
+        .await
+        .map_or(Ok("KO"), |_| Ok("OK"))
+}
+
+async fn delete_retention_policy(
+    credentials: Credentials,
+    State(state): State<RoadworkServerData>,
+    Path((team_name, opendata_service)): Path<(String, String)>,
+) -> Result<&'static str, StatusCode> {
+    info!("delete_retention_policy {} {}", team_name, opendata_service);
+    check_admin(&state.admin_service, &credentials).await?;
+    if opendata_service == ALL_SERVICES {
+        check_team(&state, &team_name).await?;
+    } else {
+        check_dataset(&state, &team_name, &opendata_service).await?;
+    }
+    state
+        .data_service
+        .delete_retention_policy(&team_name, &opendata_service)
+        .await
+        .map_or(Ok("KO"), |_| Ok("OK"))
+}
+
+/// List the entries the next retention run would archive and remove, without removing them
+async fn retention_dry_run(
+    credentials: Credentials,
+    State(state): State<RoadworkServerData>,
+) -> Result<Json<Vec<StaleEntry>>, StatusCode> {
+    info!("retention_dry_run");
+    check_admin(&state.admin_service, &credentials).await?;
+    state
+        .data_service
+        .find_stale_entries()
+        .await
+        .map(Json)
+        .map_err(|err| {
+            warn!("retention_dry_run failed: {}", err);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+async fn list_snapshots(
+    credentials: Credentials,
+    State(state): State<RoadworkServerData>,
+    Path((team_name, opendata_service)): Path<(String, String)>,
+) -> Result<Json<Vec<SnapshotInfo>>, StatusCode> {
+    info!("list_snapshots {} {}", team_name, opendata_service);
+    check_admin(&state.admin_service, &credentials).await?;
+    check_dataset(&state, &team_name, &opendata_service).await?;
+    state
+        .data_service
+        .list_snapshots(&team_name, &opendata_service)
+        .await
+        .map(Json)
+        .map_err(|err| {
+            warn!("list_snapshots failed: {}", err);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+async fn diff_snapshots(
+    credentials: Credentials,
+    State(state): State<RoadworkServerData>,
+    Path((team_name, opendata_service, from, to)): Path<(String, String, i64, i64)>,
+) -> Result<Json<SnapshotDiff>, StatusCode> {
+    info!(
+        "diff_snapshots {} {} {} {}",
+        team_name, opendata_service, from, to
+    );
+    check_admin(&state.admin_service, &credentials).await?;
+    check_dataset(&state, &team_name, &opendata_service).await?;
+    state
+        .data_service
+        .diff_snapshots(&team_name, &opendata_service, from, to)
+        .await
+        .map_err(|err| {
+            warn!("diff_snapshots failed: {}", err);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Roll the live dataset back to a snapshot
+async fn restore_snapshot(
+    credentials: Credentials,
+    State(state): State<RoadworkServerData>,
+    Path((team_name, opendata_service, snapshot_id)): Path<(String, String, i64)>,
+) -> Result<&'static str, StatusCode> {
+    info!(
+        "restore_snapshot {} {} {}",
+        team_name, opendata_service, snapshot_id
+    );
+    let username = check_admin(&state.admin_service, &credentials).await?;
+    check_dataset(&state, &team_name, &opendata_service).await?;
+    match state
+        .data_service
+        .restore_snapshot(&team_name, &opendata_service, snapshot_id, &username)
+        .await
+    {
+        Ok(Some(())) => Ok("OK"),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(err) => {
+            warn!("restore_snapshot failed: {}", err);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn list_opendata_services(
+    credentials: Credentials,
+    State(state): State<RoadworkServerData>,
+) -> Result<Json<Vec<OpendataService>>, StatusCode> {
+    info!("list_opendata_services");
+    check_admin(&state.admin_service, &credentials).await?;
+    state
+        .data_service
+        .list_opendata_services()
+        .await
+        .map(Json)
+        .map_err(|err| {
+            warn!("list_opendata_services failed: {}", err);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+/// Add an opendata service to the catalog or update it
+async fn save_opendata_service(
+    credentials: Credentials,
+    State(state): State<RoadworkServerData>,
+    Json(opendata_service): Json<OpendataService>,
+) -> Result<&'static str, StatusCode> {
+    info!("save_opendata_service {:?}", opendata_service);
+    check_admin(&state.admin_service, &credentials).await?;
+    state
+        .data_service
+        .save_opendata_service(&opendata_service)
+        .await
+        .map_or(Ok("KO"), |_| Ok("OK"))
+}
+
+async fn delete_opendata_service(
+    credentials: Credentials,
+    State(state): State<RoadworkServerData>,
+    Path(opendata_service): Path<String>,
+) -> Result<&'static str, StatusCode> {
+    info!("delete_opendata_service {}", opendata_service);
+    check_admin(&state.admin_service, &credentials).await?;
+    state
+        .data_service
+        .delete_opendata_service(&opendata_service)
+        .await
+        .map_or(Ok("KO"), |_| Ok("OK"))
+}
+
+#[derive(Debug, Serialize)]
+struct MergeStrategies {
+    /// The names of the available merge strategies
+    available: Vec<&'static str>,
+    /// The merge strategy by opendata service, the other services use the default one
+    services: HashMap<String, String>,
+}
+
+async fn list_merge_strategies(
+    credentials: Credentials,
+    State(state): State<RoadworkServerData>,
+) -> Result<Json<MergeStrategies>, StatusCode> {
+    info!("list_merge_strategies");
+    check_admin(&state.admin_service, &credentials).await?;
+    let services = state
+        .data_service
+        .list_merge_strategies()
+        .await
+        .map_err(|err| {
+            warn!("list_merge_strategies failed: {}", err);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    Ok(Json(MergeStrategies {
+        available: merge_strategy_names(),
+        services,
+    }))
+}
+
+async fn set_merge_strategy(
+    credentials: Credentials,
+    State(state): State<RoadworkServerData>,
+    Path(opendata_service): Path<String>,
+    merge_strategy: String,
+) -> Result<&'static str, StatusCode> {
+    info!("set_merge_strategy {} {}", opendata_service, merge_strategy);
+    check_admin(&state.admin_service, &credentials).await?;
+    state
+        .data_service
+        .set_merge_strategy(&opendata_service, merge_strategy.trim())
+        .await
+        .map_or(Ok("KO"), |_| Ok("OK"))
+}
+
+async fn delete_merge_strategy(
+    credentials: Credentials,
+    State(state): State<RoadworkServerData>,
+    Path(opendata_service): Path<String>,
+) -> Result<&'static str, StatusCode> {
+    info!("delete_merge_strategy {}", opendata_service);
+    check_admin(&state.admin_service, &credentials).await?;
+    state
+        .data_service
+        .delete_merge_strategy(&opendata_service)
+        .await
+        .map_or(Ok("KO"), |_| Ok("OK"))
+}
+
+/// The datasets refusing the syncs because corrupted data was found
+async fn list_quarantined(
+    credentials: Credentials,
+    State(state): State<RoadworkServerData>,
+) -> Result<Json<Vec<QuarantinedDataset>>, StatusCode> {
+    info!("list_quarantined");
+    check_admin(&state.admin_service, &credentials).await?;
+    state
+        .data_service
+        .list_quarantined()
+        .await
+        .map(Json)
+        .map_err(|err| {
+            warn!("list_quarantined failed: {}", err);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+/// Drop the corrupt entries of a quarantined dataset and accept the syncs again
+async fn repair_quarantined(
+    credentials: Credentials,
+    State(state): State<RoadworkServerData>,
+    Path((team_name, opendata_service)): Path<(String, String)>,
+) -> Result<&'static str, StatusCode> {
+    info!("repair_quarantined {} {}", team_name, opendata_service);
+    check_admin(&state.admin_service, &credentials).await?;
+    check_dataset(&state, &team_name, &opendata_service).await?;
+    state
+        .data_service
+        .repair_quarantined(&team_name, &opendata_service)
+        .await
+        .map_or(Ok("KO"), |_| Ok("OK"))
+}
+
+/// Roll a quarantined dataset back to its latest snapshot and accept the syncs again
+async fn restore_quarantined(
+    credentials: Credentials,
+    State(state): State<RoadworkServerData>,
+    Path((team_name, opendata_service)): Path<(String, String)>,
+) -> Result<&'static str, StatusCode> {
+    info!("restore_quarantined {} {}", team_name, opendata_service);
+    let username = check_admin(&state.admin_service, &credentials).await?;
+    check_dataset(&state, &team_name, &opendata_service).await?;
+    match state
+        .data_service
+        .restore_quarantined(&team_name, &opendata_service, &username)
+        .await
+    {
+        Ok(Some(_)) => Ok("OK"),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(err) => {
+            warn!("restore_quarantined failed: {}", err);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// The raw data of the corrupt entries of a dataset, kept after a repair or a restore
+async fn list_quarantined_entries(
+    credentials: Credentials,
+    State(state): State<RoadworkServerData>,
+    Path((team_name, opendata_service)): Path<(String, String)>,
+) -> Result<Json<Vec<QuarantinedEntry>>, StatusCode> {
+    info!(
+        "list_quarantined_entries {} {}",
+        team_name, opendata_service
+    );
+    check_admin(&state.admin_service, &credentials).await?;
+    check_dataset(&state, &team_name, &opendata_service).await?;
+    state
+        .data_service
+        .list_quarantined_entries(&team_name, &opendata_service)
+        .await
+        .map(Json)
+        .map_err(|err| {
+            warn!("list_quarantined_entries failed: {}", err);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+/// Forget the raw data of the corrupt entries of a dataset
+async fn purge_quarantined_entries(
+    credentials: Credentials,
+    State(state): State<RoadworkServerData>,
+    Path((team_name, opendata_service)): Path<(String, String)>,
+) -> Result<&'static str, StatusCode> {
+    info!(
+        "purge_quarantined_entries {} {}",
+        team_name, opendata_service
+    );
+    check_admin(&state.admin_service, &credentials).await?;
+    check_dataset(&state, &team_name, &opendata_service).await?;
+    state
+        .data_service
+        .purge_quarantined_entries(&team_name, &opendata_service)
+        .await
+        .map_or(Ok("KO"), |_| Ok("OK"))
+}
+
+/// The webhooks of every team, without their secret
+async fn list_webhooks(
+    credentials: Credentials,
+    State(state): State<RoadworkServerData>,
+) -> Result<Json<Vec<Webhook>>, StatusCode> {
+    info!("list_webhooks");
+    check_admin(&state.admin_service, &credentials).await?;
+    state
+        .webhook_service
+        .list_webhooks()
+        .await
+        .map(Json)
+        .map_err(|err| {
+            warn!("list_webhooks failed: {}", err);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+/// Register a webhook, returns its id
+async fn add_webhook(
+    credentials: Credentials,
+    State(state): State<RoadworkServerData>,
+    Json(webhook): Json<Webhook>,
+) -> Result<Json<i64>, StatusCode> {
+    info!("add_webhook {} {}", webhook.team, webhook.url);
+    check_admin(&state.admin_service, &credentials).await?;
+    state
+        .webhook_service
+        .add_webhook(&webhook)
+        .await
+        .map(Json)
+        .map_err(|err| {
+            warn!("add_webhook failed: {}", err);
+            StatusCode::BAD_REQUEST
+        })
+}
+
+async fn delete_webhook(
+    credentials: Credentials,
+    State(state): State<RoadworkServerData>,
+    Path(webhook_id): Path<i64>,
+) -> Result<&'static str, StatusCode> {
+    info!("delete_webhook {}", webhook_id);
+    check_admin(&state.admin_service, &credentials).await?;
+    state
+        .webhook_service
+        .delete_webhook(webhook_id)
+        .await
+        .map_or(Ok("KO"), |_| Ok("OK"))
+}
+
+/// The latest delivery attempts of a webhook, newest first
+async fn list_webhook_deliveries(
+    credentials: Credentials,
+    State(state): State<RoadworkServerData>,
+    Path(webhook_id): Path<i64>,
+) -> Result<Json<Vec<WebhookDelivery>>, StatusCode> {
+    info!("list_webhook_deliveries {}", webhook_id);
+    check_admin(&state.admin_service, &credentials).await?;
+    state
+        .webhook_service
+        .list_deliveries(webhook_id)
+        .await
+        .map(Json)
+        .map_err(|err| {
+            warn!("list_webhook_deliveries failed: {}", err);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+/// Send a ping event to a webhook and return the delivery
+async fn ping_webhook(
+    credentials: Credentials,
+    State(state): State<RoadworkServerData>,
+    Path(webhook_id): Path<i64>,
+) -> Result<Json<WebhookDelivery>, StatusCode> {
+    info!("ping_webhook {}", webhook_id);
+    check_admin(&state.admin_service, &credentials).await?;
+    state
+        .webhook_service
+        .ping(webhook_id)
+        .await
+        .map_err(|err| {
+            warn!("ping_webhook failed: {}", err);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+async fn list_feeds(
+    credentials: Credentials,
+    State(state): State<RoadworkServerData>,
+) -> Result<Json<Vec<OpendataFeed>>, StatusCode> {
+    info!("list_feeds");
+    check_admin(&state.admin_service, &credentials).await?;
+    state
+        .feed_service
+        .list_feeds()
+        .await
+        .map(Json)
+        .map_err(|err| {
+            warn!("list_feeds failed: {}", err);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+/// Add or replace the feed of an opendata service
+async fn save_feed(
+    credentials: Credentials,
+    State(state): State<RoadworkServerData>,
+    Json(feed): Json<OpendataFeed>,
+) -> Result<&'static str, StatusCode> {
+    info!("save_feed {} {}", feed.service, feed.url);
+    check_admin(&state.admin_service, &credentials).await?;
+    state.feed_service.save_feed(&feed).await.map_err(|err| {
+        warn!("save_feed failed: {}", err);
+        StatusCode::BAD_REQUEST
+    })?;
+    Ok("OK")
+}
+
+async fn delete_feed(
+    credentials: Credentials,
+    State(state): State<RoadworkServerData>,
+    Path(opendata_service): Path<String>,
+) -> Result<&'static str, StatusCode> {
+    info!("delete_feed {}", opendata_service);
+    check_admin(&state.admin_service, &credentials).await?;
+    state
+        .feed_service
+        .delete_feed(&opendata_service)
+        .await
+        .map_or(Ok("KO"), |_| Ok("OK"))
+}
+
+/// Pull the feed of an opendata service now instead of waiting for the next scheduled pull
+async fn run_feed(
+    credentials: Credentials,
+    State(state): State<RoadworkServerData>,
+    Path(opendata_service): Path<String>,
+) -> Result<Json<FeedReport>, StatusCode> {
+    info!("run_feed {}", opendata_service);
+    check_admin(&state.admin_service, &credentials).await?;
+    state
+        .feed_service
+        .run_feed(&opendata_service)
+        .await
+        .map_err(|err| {
+            warn!("run_feed failed: {}", err);
+            StatusCode::BAD_GATEWAY
+        })?
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
 async fn check_admin(
     admin_service: &AdminService,
-    username: &String,
-    password: &Option<String>,
+    credentials: &Credentials,
+) -> Result<String, StatusCode> {
+    if let Some(admin) = admin_service.find_admin(credentials).await {
+        return Ok(admin.username);
+    }
+    warn!("User {} is not an admin", credentials);
+    Err(StatusCode::UNAUTHORIZED)
+}
+
+/// Check the team exists, the team name reaches the data store
+async fn check_team(state: &RoadworkServerData, team_name: &str) -> Result<(), StatusCode> {
+    match state.user_repository.team_exists(team_name).await {
+        Ok(true) => Ok(()),
+        Ok(false) => {
+            warn!("Unknown team {}", team_name);
+            Err(StatusCode::NOT_FOUND)
+        }
+        Err(err) => {
+            warn!("check_team failed: {}", err);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Check the team exists and the opendata service is in the catalog,
+/// like the roadwork routes as both names reach the data store
+async fn check_dataset(
+    state: &RoadworkServerData,
+    team_name: &str,
+    opendata_service: &str,
 ) -> Result<(), StatusCode> {
-    if let Some(password) = password {
-        if admin_service.is_admin(username, password).await {
-            return Ok(());
+    check_team(state, team_name).await?;
+    if !is_valid_opendata_service_name(opendata_service) {
+        warn!("Malformed opendata service name {}", opendata_service);
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    match state
+        .data_service
+        .find_opendata_service(opendata_service)
+        .await
+    {
+        Ok(Some(_)) => Ok(()),
+        Ok(None) => {
+            warn!("Unknown opendata service {}", opendata_service);
+            Err(StatusCode::NOT_FOUND)
+        }
+        Err(err) => {
+            warn!("check_dataset failed: {}", err);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
         }
     }
-    warn!("User {} is not an admin", username);
-    Err(StatusCode::UNAUTHORIZED)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::router::test_server;
+
+    async fn get_status(url: &str) -> u16 {
+        reqwest::Client::new()
+            .get(url)
+            .basic_auth(test_server::ADMIN.0, Some(test_server::ADMIN.1))
+            .send()
+            .await
+            .unwrap()
+            .status()
+            .as_u16()
+    }
+
+    /// The team and the service of the path are checked before they reach the data store
+    #[tokio::test]
+    async fn refuse_unknown_datasets() {
+        let addr = test_server::serve().await;
+        let snapshots = |team: &str, service: &str| {
+            format!("http://{}/admin/snapshots/{}/{}", addr, team, service)
+        };
+        assert_eq!(get_status(&snapshots("admin", "service")).await, 200);
+        assert_eq!(get_status(&snapshots("admin", "unknown")).await, 404);
+        assert_eq!(get_status(&snapshots("admin", "..%2Fservice")).await, 400);
+        assert_eq!(get_status(&snapshots("unknown", "service")).await, 404);
+        assert_eq!(get_status(&snapshots("..%2Fadmin", "service")).await, 404);
+    }
 }
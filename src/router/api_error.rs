@@ -0,0 +1,61 @@
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+
+use crate::error::DataError;
+
+/// An error returned to the client, with an optional message explaining it
+#[derive(Debug)]
+pub(crate) struct ApiError {
+    status: StatusCode,
+    message: Option<String>,
+}
+
+impl ApiError {
+    pub(crate) fn new<S: Into<String>>(status: StatusCode, message: S) -> Self {
+        ApiError {
+            status,
+            message: Some(message.into()),
+        }
+    }
+
+    pub(crate) fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// The message, or the reason of the status when there is none
+    pub(crate) fn message(&self) -> String {
+        self.message.clone().unwrap_or_else(|| {
+            self.status
+                .canonical_reason()
+                .unwrap_or_default()
+                .to_string()
+        })
+    }
+}
+
+impl From<StatusCode> for ApiError {
+    fn from(status: StatusCode) -> Self {
+        ApiError {
+            status,
+            message: None,
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        match self.message {
+            Some(message) => (self.status, message).into_response(),
+            None => self.status.into_response(),
+        }
+    }
+}
+
+impl From<DataError> for ApiError {
+    fn from(err: DataError) -> Self {
+        match err {
+            DataError::Quarantined(_, _) => ApiError::new(StatusCode::LOCKED, err.to_string()),
+            DataError::Storage(_) => StatusCode::INTERNAL_SERVER_ERROR.into(),
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use std::net::SocketAddr;
+
+use axum::extract::{ConnectInfo, FromRequestParts};
+use axum::http::StatusCode;
+use axum::http::header::{AUTHORIZATION, RETRY_AFTER};
+use axum::http::request::Parts;
+use axum::response::{IntoResponse, Response};
+use axum_auth::AuthBasic;
+use log::warn;
+
+use crate::RoadworkServerData;
+use crate::service::user::{API_KEY_PREFIX, Credentials};
+
+/// Accepts an access token from /user/login or a team api key as a bearer token,
+/// or the username and password as basic auth.
+/// The passwords of a throttled username or client are rejected with 429 without being checked
+impl FromRequestParts<RoadworkServerData> for Credentials {
+    type Rejection = Response;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &RoadworkServerData,
+    ) -> Result<Self, Self::Rejection> {
+        let access_token = parts
+            .headers
+            .get(AUTHORIZATION)
+            .and_then(|authorization| authorization.to_str().ok())
+            .and_then(|authorization| authorization.strip_prefix("Bearer "));
+        if let Some(access_token) = access_token {
+            let access_token = access_token.trim().to_string();
+            if access_token.starts_with(API_KEY_PREFIX) {
+                return Ok(Credentials::ApiKey(access_token));
+            }
+            return Ok(Credentials::Bearer(access_token));
+        }
+        let AuthBasic((username, password)) = AuthBasic::from_request_parts(parts, state)
+            .await
+            .map_err(IntoResponse::into_response)?;
+        let client = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(address)| address.ip());
+        if let Some(retry_after) = state.admin_service.retry_after(&username, client) {
+            warn!(
+                "User {} from {:?} is throttled for {:?}",
+                username, client, retry_after
+            );
+            return Err((
+                StatusCode::TOO_MANY_REQUESTS,
+                [(RETRY_AFTER, (retry_after.as_secs() + 1).to_string())],
+                "Too many failed attempts, try again later",
+            )
+                .into_response());
+        }
+        Ok(Credentials::Basic(username, password, client))
+    }
+}
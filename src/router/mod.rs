@@ -1,3 +1,9 @@
 pub(crate) mod admin;
+pub(crate) mod api_error;
+mod auth;
 pub(crate) mod roadwork;
+pub(crate) mod team;
+/// A server with the routes for the tests
+#[cfg(test)]
+pub(crate) mod test_server;
 pub(crate) mod user;
@@ -1,43 +1,513 @@
 use std::collections::HashMap;
 
-use axum::extract::{Path, State};
-use axum::http::StatusCode;
-use axum::routing::post;
+use axum::extract::{Path, Query, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{delete, get, post};
 use axum::{Json, Router};
-use axum_auth::AuthBasic;
 use log::warn;
 use roadwork_sync_lib::sync_data::SyncData;
-use crate::service::data;
+use serde::Deserialize;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+use tokio_stream::{Stream, StreamExt};
+use crate::router::api_error::ApiError;
+use crate::service::data::opendata_service::is_valid_opendata_service_name;
+use crate::service::data::{
+    AttributedData, BatchError, BatchSetDataResponse, DeltaSyncRequest, DeltaSyncResponse,
+};
+use crate::service::data_repository::{DatasetInfo, StatusChange};
+use crate::service::export;
+use crate::service::user::{Access, Credentials, TeamMember};
+use crate::service::export::ExportFormat;
 use crate::{info, RoadworkServerData};
 
 pub(crate) fn roadwork_routes() -> Router<RoadworkServerData> {
-    Router::new().route("/set_data/{team}/{opendata_service}", post(set_data))
+    Router::new()
+        .route("/set_data/{team}", post(set_data_batch))
+        .route("/set_data/{team}/{opendata_service}", post(set_data))
+        .route("/data/{team}/{opendata_service}", get(get_data))
+        .route("/data/{team}/{opendata_service}/{id}", delete(delete_data))
+        .route("/sync/{team}/{opendata_service}", post(sync_delta))
+        .route("/history/{team}/{opendata_service}/{id}", get(get_history))
+        .route("/services/{team}", get(list_services))
+        .route("/export/{team}/{opendata_service}", get(export_data))
+        .route("/subscribe/{team}/{opendata_service}", get(subscribe))
+}
+
+/// Query parameters of set_data
+#[derive(Debug, Deserialize)]
+pub(crate) struct SetDataParams {
+    /// The response contains the merged data, the deleted ids and the conflict report.
+    /// When false it contains the merged data only, for the clients written before the report
+    #[serde(default = "report_by_default")]
+    report: bool,
+}
+
+fn report_by_default() -> bool {
+    true
+}
+
+/// Query parameters of get_data
+#[derive(Debug, Deserialize)]
+pub(crate) struct GetDataParams {
+    /// When true the response contains the data and the last modifying user of each entry
+    /// instead of the data only
+    #[serde(default)]
+    attribution: bool,
+}
+
+/// Query parameters of export_data
+#[derive(Debug, Deserialize)]
+pub(crate) struct ExportParams {
+    /// csv or geojson, takes precedence over the Accept header
+    format: Option<String>,
 }
 
 pub(crate) async fn set_data(
-    AuthBasic((username, password)): AuthBasic,
+    credentials: Credentials,
     State(state): State<RoadworkServerData>,
     Path((team, opendata_service)): Path<(String, String)>,
+    Query(params): Query<SetDataParams>,
     Json(sync_data_list): Json<HashMap<String, SyncData>>,
-) -> Result<Json<HashMap<String, SyncData>>, StatusCode> {
-    let password: String = password.unwrap_or_else(|| "".to_string());
-    return if state
-        .admin_service
-        .has_team(&username, &password, &team)
-        .await
-    {
+) -> Result<Response, ApiError> {
+    return if let Some(member) = state.admin_service.team_member(&credentials, &team).await {
         info!(
             "set_data user={} team={} service={}",
-            username, team, opendata_service
+            member.username, team, opendata_service
         );
-        let opendata_service = opendata_service
-            .strip_suffix(".json")
-            .unwrap_or(&opendata_service);
-
-        let string_sync_data_map = data::set_data(team.as_str(), opendata_service, sync_data_list);
-        Ok(Json(string_sync_data_map))
+        let opendata_service = check_opendata_service(&state, &opendata_service).await?;
+        check_access(&member, &opendata_service, Access::Write)?;
+        let set_data_response = state
+            .data_service
+            .set_data(
+                team.as_str(),
+                &opendata_service,
+                &member.username,
+                sync_data_list,
+            )
+            .await
+            .map_err(|err| {
+                warn!("set_data failed: {}", err);
+                ApiError::from(err)
+            })?;
+        if params.report {
+            Ok(Json(set_data_response).into_response())
+        } else {
+            Ok(Json(set_data_response.data).into_response())
+        }
     } else {
-        warn!("User {} is not valid for team {}", username, team);
-        Err(StatusCode::UNAUTHORIZED)
+        warn!("User {} is not valid for team {}", credentials, team);
+        Err(StatusCode::UNAUTHORIZED.into())
     };
 }
+
+/// Sync several opendata services of a team in one request, each service is merged like set_data.
+/// A failing service does not prevent the others from being merged
+pub(crate) async fn set_data_batch(
+    credentials: Credentials,
+    State(state): State<RoadworkServerData>,
+    Path(team): Path<String>,
+    Json(batch): Json<HashMap<String, HashMap<String, SyncData>>>,
+) -> Result<Json<BatchSetDataResponse>, ApiError> {
+    if let Some(member) = state.admin_service.team_member(&credentials, &team).await {
+        info!(
+            "set_data_batch user={} team={} services={:?}",
+            member.username,
+            team,
+            batch.keys()
+        );
+        let mut batch_response = BatchSetDataResponse::default();
+        for (opendata_service, sync_data_list) in batch {
+            let result = match check_opendata_service(&state, &opendata_service)
+                .await
+                .and_then(|checked_opendata_service| {
+                    check_access(&member, &checked_opendata_service, Access::Write)
+                        .map(|_| checked_opendata_service)
+                }) {
+                Ok(checked_opendata_service) => state
+                    .data_service
+                    .set_data(
+                        team.as_str(),
+                        &checked_opendata_service,
+                        &member.username,
+                        sync_data_list,
+                    )
+                    .await
+                    .map_err(|err| {
+                        warn!("set_data_batch failed for {}: {}", opendata_service, err);
+                        ApiError::from(err)
+                    }),
+                Err(err) => Err(err),
+            };
+            match result {
+                Ok(set_data_response) => {
+                    batch_response
+                        .results
+                        .insert(opendata_service, set_data_response);
+                }
+                Err(err) => {
+                    batch_response.errors.insert(
+                        opendata_service,
+                        BatchError {
+                            status: err.status().as_u16(),
+                            message: err.message(),
+                        },
+                    );
+                }
+            }
+        }
+        Ok(Json(batch_response))
+    } else {
+        warn!("User {} is not valid for team {}", credentials, team);
+        Err(StatusCode::UNAUTHORIZED.into())
+    }
+}
+
+pub(crate) async fn get_data(
+    credentials: Credentials,
+    State(state): State<RoadworkServerData>,
+    Path((team, opendata_service)): Path<(String, String)>,
+    Query(params): Query<GetDataParams>,
+) -> Result<Response, ApiError> {
+    if let Some(member) = state.admin_service.team_member(&credentials, &team).await {
+        info!(
+            "get_data user={} team={} service={}",
+            member.username, team, opendata_service
+        );
+        let opendata_service = check_opendata_service(&state, &opendata_service).await?;
+        check_access(&member, &opendata_service, Access::Read)?;
+        let string_sync_data_map = state
+            .data_service
+            .get_data(team.as_str(), &opendata_service)
+            .await
+            .map_err(|err| {
+                warn!("get_data failed: {}", err);
+                ApiError::from(err)
+            })?;
+        if params.attribution {
+            let attribution = state
+                .data_service
+                .get_attribution(team.as_str(), &opendata_service)
+                .await
+                .map_err(|err| {
+                    warn!("get_data failed: {}", err);
+                    StatusCode::INTERNAL_SERVER_ERROR
+                })?;
+            Ok(Json(AttributedData {
+                data: string_sync_data_map,
+                attribution,
+            })
+            .into_response())
+        } else {
+            Ok(Json(string_sync_data_map).into_response())
+        }
+    } else {
+        warn!("User {} is not valid for team {}", credentials, team);
+        Err(StatusCode::UNAUTHORIZED.into())
+    }
+}
+
+/// Delta sync: the client sends its dirty entries and receives the entries modified since its cursor
+pub(crate) async fn sync_delta(
+    credentials: Credentials,
+    State(state): State<RoadworkServerData>,
+    Path((team, opendata_service)): Path<(String, String)>,
+    Json(delta_sync_request): Json<DeltaSyncRequest>,
+) -> Result<Json<DeltaSyncResponse>, ApiError> {
+    if let Some(member) = state.admin_service.team_member(&credentials, &team).await {
+        info!(
+            "sync_delta user={} team={} service={}",
+            member.username, team, opendata_service
+        );
+        let opendata_service = check_opendata_service(&state, &opendata_service).await?;
+        check_access(&member, &opendata_service, Access::Write)?;
+        let delta_sync_response = state
+            .data_service
+            .sync_delta(
+                team.as_str(),
+                &opendata_service,
+                &member.username,
+                delta_sync_request,
+            )
+            .await
+            .map_err(|err| {
+                warn!("sync_delta failed: {}", err);
+                ApiError::from(err)
+            })?;
+        Ok(Json(delta_sync_response))
+    } else {
+        warn!("User {} is not valid for team {}", credentials, team);
+        Err(StatusCode::UNAUTHORIZED.into())
+    }
+}
+
+/// The status history of one roadwork entry
+pub(crate) async fn get_history(
+    credentials: Credentials,
+    State(state): State<RoadworkServerData>,
+    Path((team, opendata_service, id)): Path<(String, String, String)>,
+) -> Result<Json<Vec<StatusChange>>, ApiError> {
+    if let Some(member) = state.admin_service.team_member(&credentials, &team).await {
+        info!(
+            "get_history user={} team={} service={} id={}",
+            member.username, team, opendata_service, id
+        );
+        let opendata_service = check_opendata_service(&state, &opendata_service).await?;
+        check_access(&member, &opendata_service, Access::Read)?;
+        let history = state
+            .data_service
+            .get_history(team.as_str(), &opendata_service, id.as_str())
+            .await
+            .map_err(|err| {
+                warn!("get_history failed: {}", err);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+        Ok(Json(history))
+    } else {
+        warn!("User {} is not valid for team {}", credentials, team);
+        Err(StatusCode::UNAUTHORIZED.into())
+    }
+}
+
+/// Delete a roadwork entry, the other clients receive the deletion on their next sync
+pub(crate) async fn delete_data(
+    credentials: Credentials,
+    State(state): State<RoadworkServerData>,
+    Path((team, opendata_service, id)): Path<(String, String, String)>,
+) -> Result<StatusCode, ApiError> {
+    if let Some(member) = state.admin_service.team_member(&credentials, &team).await {
+        info!(
+            "delete_data user={} team={} service={} id={}",
+            member.username, team, opendata_service, id
+        );
+        let opendata_service = check_opendata_service(&state, &opendata_service).await?;
+        check_access(&member, &opendata_service, Access::Write)?;
+        state
+            .data_service
+            .delete(team.as_str(), &opendata_service, &member.username, &[id])
+            .await
+            .map_err(|err| {
+                warn!("delete_data failed: {}", err);
+                ApiError::from(err)
+            })?;
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        warn!("User {} is not valid for team {}", credentials, team);
+        Err(StatusCode::UNAUTHORIZED.into())
+    }
+}
+
+/// The opendata services the team has data for, with their entry count and last sync
+pub(crate) async fn list_services(
+    credentials: Credentials,
+    State(state): State<RoadworkServerData>,
+    Path(team): Path<String>,
+) -> Result<Json<Vec<DatasetInfo>>, ApiError> {
+    if let Some(member) = state.admin_service.team_member(&credentials, &team).await {
+        info!("list_services user={} team={}", member.username, team);
+        let mut datasets = state
+            .data_service
+            .list_datasets(team.as_str())
+            .await
+            .map_err(|err| {
+                warn!("list_services failed: {}", err);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+        datasets.retain(|dataset| member.can_access(&dataset.service, Access::Read));
+        Ok(Json(datasets))
+    } else {
+        warn!("User {} is not valid for team {}", credentials, team);
+        Err(StatusCode::UNAUTHORIZED.into())
+    }
+}
+
+/// Export a team dataset as CSV or GeoJSON, chosen by the format parameter or the Accept header.
+/// CSV is used when neither asks for a known format
+pub(crate) async fn export_data(
+    credentials: Credentials,
+    State(state): State<RoadworkServerData>,
+    Path((team, opendata_service)): Path<(String, String)>,
+    Query(params): Query<ExportParams>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    if let Some(member) = state.admin_service.team_member(&credentials, &team).await {
+        info!(
+            "export_data user={} team={} service={} format={:?}",
+            member.username, team, opendata_service, params.format
+        );
+        let opendata_service = check_opendata_service(&state, &opendata_service).await?;
+        check_access(&member, &opendata_service, Access::Read)?;
+        let format = match params.format {
+            Some(format) => ExportFormat::from_name(&format).ok_or(StatusCode::BAD_REQUEST)?,
+            None => headers
+                .get(header::ACCEPT)
+                .and_then(|accept| accept.to_str().ok())
+                .and_then(ExportFormat::from_accept)
+                .unwrap_or(ExportFormat::Csv),
+        };
+        let string_sync_data_map = state
+            .data_service
+            .get_data(team.as_str(), &opendata_service)
+            .await
+            .map_err(|err| {
+                warn!("export_data failed: {}", err);
+                ApiError::from(err)
+            })?;
+        let attribution = state
+            .data_service
+            .get_attribution(team.as_str(), &opendata_service)
+            .await
+            .map_err(|err| {
+                warn!("export_data failed: {}", err);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+        let content_disposition = format!(
+            "attachment; filename=\"{}.{}\"",
+            opendata_service,
+            format.extension()
+        );
+        Ok((
+            [
+                (header::CONTENT_TYPE, format.content_type().to_string()),
+                (header::CONTENT_DISPOSITION, content_disposition),
+            ],
+            export::export(&string_sync_data_map, &attribution, format),
+        )
+            .into_response())
+    } else {
+        warn!("User {} is not valid for team {}", credentials, team);
+        Err(StatusCode::UNAUTHORIZED.into())
+    }
+}
+
+/// Server-Sent Events stream of the changes merged into a team dataset.
+/// A "change" event carries the modified and deleted entries, a "resync" event tells
+/// the client it missed changes and must sync the whole dataset
+pub(crate) async fn subscribe(
+    credentials: Credentials,
+    State(state): State<RoadworkServerData>,
+    Path((team, opendata_service)): Path<(String, String)>,
+) -> Result<Sse<impl Stream<Item = Result<Event, axum::Error>>>, ApiError> {
+    if let Some(member) = state.admin_service.team_member(&credentials, &team).await {
+        info!(
+            "subscribe user={} team={} service={}",
+            member.username, team, opendata_service
+        );
+        let opendata_service = check_opendata_service(&state, &opendata_service).await?;
+        check_access(&member, &opendata_service, Access::Read)?;
+        let changes = BroadcastStream::new(state.data_service.subscribe());
+        let stream = changes.filter_map(move |change| match change {
+            Ok(change) if change.team == team && change.service == opendata_service => {
+                Some(Event::default().event("change").json_data(change))
+            }
+            Ok(_) => None,
+            Err(BroadcastStreamRecvError::Lagged(count)) => {
+                warn!("subscriber lagged, {} changes were missed", count);
+                Some(Ok(Event::default().event("resync").data(count.to_string())))
+            }
+        });
+        Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+    } else {
+        warn!("User {} is not valid for team {}", credentials, team);
+        Err(StatusCode::UNAUTHORIZED.into())
+    }
+}
+
+/// Check an api key is allowed on the opendata service, the members of the team are allowed on all of them
+fn check_access(
+    member: &TeamMember,
+    opendata_service: &str,
+    access: Access,
+) -> Result<(), ApiError> {
+    if member.can_access(opendata_service, access) {
+        return Ok(());
+    }
+    warn!("{} is not allowed on {}", member.username, opendata_service);
+    Err(ApiError::new(
+        StatusCode::FORBIDDEN,
+        format!("{} is not allowed on {}", member.username, opendata_service),
+    ))
+}
+
+/// Normalize the opendata service of the path and check it is in the catalog
+async fn check_opendata_service(
+    state: &RoadworkServerData,
+    opendata_service: &str,
+) -> Result<String, ApiError> {
+    let opendata_service = opendata_service
+        .strip_suffix(".json")
+        .unwrap_or(opendata_service);
+    if !is_valid_opendata_service_name(opendata_service) {
+        warn!("Malformed opendata service name {}", opendata_service);
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            format!("Malformed opendata service name {}", opendata_service),
+        ));
+    }
+    match state
+        .data_service
+        .find_opendata_service(opendata_service)
+        .await
+    {
+        Ok(Some(_)) => Ok(opendata_service.to_string()),
+        Ok(None) => {
+            warn!("Unknown opendata service {}", opendata_service);
+            Err(ApiError::new(
+                StatusCode::NOT_FOUND,
+                format!("Unknown opendata service {}", opendata_service),
+            ))
+        }
+        Err(err) => {
+            warn!("check_opendata_service failed: {}", err);
+            Err(StatusCode::INTERNAL_SERVER_ERROR.into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::header::CONTENT_TYPE;
+    use serde_json::{Value, json};
+
+    use crate::router::test_server;
+
+    async fn set_data(url: &str, status: &str) -> Value {
+        let sync_data_list = json!({
+            "1": {"localUpdateTime": 1, "serverUpdateTime": 0, "status": status, "dirty": true}
+        });
+        let response = reqwest::Client::new()
+            .post(url)
+            .basic_auth(test_server::ADMIN.0, Some(test_server::ADMIN.1))
+            .header(CONTENT_TYPE, "application/json")
+            .body(sync_data_list.to_string())
+            .send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        serde_json::from_str(&response).unwrap()
+    }
+
+    /// The conflicts are reported unless the client asks for the merged data only
+    #[tokio::test]
+    async fn set_data_reports_conflicts_by_default() {
+        let addr = test_server::serve().await;
+        let url = format!("http://{}/roadwork/set_data/admin/service", addr);
+        set_data(&url, "Ongoing").await;
+
+        let response = set_data(&url, "New").await;
+        assert_eq!(response["data"]["1"]["status"], "Ongoing");
+        assert_eq!(response["deleted"], json!([]));
+        assert_eq!(
+            response["conflicts"]["server_won"],
+            json!([{"id": "1", "client_status": "New", "server_status": "Ongoing"}])
+        );
+
+        let response = set_data(&format!("{}?report=false", url), "New").await;
+        assert_eq!(response["1"]["status"], "Ongoing");
+        assert!(response.get("conflicts").is_none());
+    }
+}
@@ -0,0 +1,86 @@
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::routing::{delete, get, post};
+use axum::{Json, Router};
+use log::{info, warn};
+
+use crate::RoadworkServerData;
+use crate::router::api_error::ApiError;
+use crate::service::user::{Credentials, IssuedApiKey};
+use crate::service::user_repository::ApiKey;
+
+/// The routes of the team admins
+pub(crate) fn team_routes() -> Router<RoadworkServerData> {
+    Router::new()
+        .route("/{team}/api_keys", get(list_api_keys))
+        .route("/{team}/api_key", post(issue_api_key))
+        .route("/{team}/api_key/{api_key_id}", delete(revoke_api_key))
+}
+
+async fn list_api_keys(
+    credentials: Credentials,
+    State(state): State<RoadworkServerData>,
+    Path(team): Path<String>,
+) -> Result<Json<Vec<ApiKey>>, ApiError> {
+    info!("list_api_keys team={}", team);
+    check_team_admin(&state, &credentials, &team).await?;
+    state
+        .admin_service
+        .list_api_keys(&team)
+        .await
+        .map(Json)
+        .map_err(|err| {
+            warn!("list_api_keys failed: {}", err);
+            StatusCode::INTERNAL_SERVER_ERROR.into()
+        })
+}
+
+/// Issue an api key, the key is only returned in the response
+async fn issue_api_key(
+    credentials: Credentials,
+    State(state): State<RoadworkServerData>,
+    Path(team): Path<String>,
+    Json(api_key): Json<ApiKey>,
+) -> Result<Json<IssuedApiKey>, ApiError> {
+    info!("issue_api_key team={} name={}", team, api_key.name);
+    let username = check_team_admin(&state, &credentials, &team).await?;
+    state
+        .admin_service
+        .issue_api_key(&team, api_key, &username)
+        .await
+        .map(Json)
+        .map_err(|err| {
+            warn!("issue_api_key failed: {}", err);
+            ApiError::new(StatusCode::BAD_REQUEST, err)
+        })
+}
+
+async fn revoke_api_key(
+    credentials: Credentials,
+    State(state): State<RoadworkServerData>,
+    Path((team, api_key_id)): Path<(String, i64)>,
+) -> Result<StatusCode, ApiError> {
+    info!("revoke_api_key team={} id={}", team, api_key_id);
+    check_team_admin(&state, &credentials, &team).await?;
+    match state.admin_service.revoke_api_key(&team, api_key_id).await {
+        Ok(true) => Ok(StatusCode::NO_CONTENT),
+        Ok(false) => Err(StatusCode::NOT_FOUND.into()),
+        Err(err) => {
+            warn!("revoke_api_key failed: {}", err);
+            Err(StatusCode::INTERNAL_SERVER_ERROR.into())
+        }
+    }
+}
+
+/// Returns the username of the team admin
+async fn check_team_admin(
+    state: &RoadworkServerData,
+    credentials: &Credentials,
+    team: &String,
+) -> Result<String, ApiError> {
+    if let Some(user) = state.admin_service.find_team_admin(credentials, team).await {
+        return Ok(user.username);
+    }
+    warn!("User {} is not an admin of team {}", credentials, team);
+    Err(StatusCode::UNAUTHORIZED.into())
+}
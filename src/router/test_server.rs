@@ -0,0 +1,74 @@
+use std::net::SocketAddr;
+
+use axum::Router;
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+
+use crate::RoadworkServerData;
+use crate::config::Config;
+use crate::hash;
+use crate::router::admin::admin_routes;
+use crate::router::roadwork::roadwork_routes;
+use crate::service::data::DataService;
+use crate::service::data_repository::{DataRepository, OpendataService};
+use crate::service::feed::FeedService;
+use crate::service::user::AdminService;
+use crate::service::user_repository::UserRepository;
+use crate::service::webhook::WebhookService;
+
+/// The admin user created with the database, its default password is changed
+/// as the admin routes refuse it
+pub(crate) const ADMIN: (&str, &str) = ("admin", "test-password");
+
+/// Serve the roadwork and admin routes on an in-memory server with the opendata service "service".
+/// Returns the address of the server
+pub(crate) async fn serve() -> SocketAddr {
+    let config = Config::for_tests();
+    let user_repository = UserRepository::new(&config).await.unwrap();
+    user_repository
+        .update_password(ADMIN.0, hash::salt(&ADMIN.1.to_string(), &config))
+        .await
+        .unwrap();
+    let admin_service = AdminService::new(user_repository.clone(), config.clone()).await;
+    let data_repository = DataRepository::new(user_repository.pool(), &config)
+        .await
+        .unwrap();
+    let webhook_service = WebhookService::new(
+        data_repository.clone(),
+        user_repository.clone(),
+        config.clone(),
+    )
+    .unwrap();
+    let (webhook_changes, _) = mpsc::unbounded_channel();
+    let data_service = DataService::new(data_repository.clone(), config.clone(), webhook_changes);
+    data_service
+        .save_opendata_service(&OpendataService {
+            id: "service".to_string(),
+            label: "Service".to_string(),
+            description: String::new(),
+            status_meaning: String::new(),
+        })
+        .await
+        .unwrap();
+    let feed_service = FeedService::new(
+        data_repository,
+        data_service.clone(),
+        user_repository.clone(),
+        config,
+    )
+    .unwrap();
+    let app = Router::new()
+        .nest("/admin", admin_routes())
+        .nest("/roadwork", roadwork_routes())
+        .with_state(RoadworkServerData {
+            user_repository,
+            admin_service,
+            data_service,
+            webhook_service,
+            feed_service,
+        });
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await });
+    addr
+}
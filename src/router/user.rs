@@ -2,9 +2,11 @@ use axum::extract::{Path, State};
 use axum::http::StatusCode;
 use axum::routing::{get, post};
 use axum::{Json, Router};
-use axum_auth::AuthBasic;
 use log::{info, warn};
 use roadwork_sync_lib::user::User;
+use serde::Deserialize;
+use crate::service::user::{Credentials, Token};
+use crate::hash::HashAlgorithm;
 use crate::{hash, RoadworkServerData};
 
 pub(crate) fn user_routes() -> Router<RoadworkServerData> {
@@ -14,48 +16,55 @@ pub(crate) fn user_routes() -> Router<RoadworkServerData> {
         .route("/check/{bcrypted}/{password}", get(check))
         .route("/salt", post(salt))
         .route("/test_connection/{teamname}", get(test_connection))
+        .route("/login", post(login))
+        .route("/refresh", post(refresh))
+        .route("/logout", post(logout))
+}
+
+/// Body of refresh
+#[derive(Debug, Deserialize)]
+pub(crate) struct RefreshRequest {
+    refresh_token: String,
 }
 
 async fn test_connection(
-    AuthBasic((username, password)): AuthBasic,
+    credentials: Credentials,
     State(state): State<RoadworkServerData>,
     Path(teamname): Path<String>,
 ) -> String {
-    info!("test_connection user={}, teamname={}", username, teamname);
-    let password: String = password.unwrap_or_else(|| "".to_string());
-    if let Some(user) = state.admin_service.get_user(&username, &password).await {
+    info!(
+        "test_connection user={}, teamname={}",
+        credentials, teamname
+    );
+    if let Some(user) = state.admin_service.authenticate(&credentials).await {
         if user.teams.contains(&teamname) {
             return "OK".to_string();
         }
-        return format!("User {} is not in team {}", username, teamname);
+        return format!("User {} is not in team {}", user.username, teamname);
     }
 
     "User is invalid".to_string()
 }
 
 async fn change_password(
-    AuthBasic((username, password)): AuthBasic,
+    credentials: Credentials,
     State(state): State<RoadworkServerData>,
     new_password: String,
 ) -> Result<StatusCode, &'static str> {
-    info!("change_password username={}", username);
+    info!("change_password username={}", credentials);
     if new_password.len() < 8 {
         warn!("Password is too short");
         return Err("Password is too short");
     }
-    if password.is_none() {
+    // a token is not enough, the current password must be given
+    let Credentials::Basic(_, Some(_), _) = credentials else {
         warn!("Password is missing");
         return Err("Password is missing");
-    }
-    let password = password.unwrap();
-    if state
-        .admin_service
-        .get_user(&username, &password)
-        .await
-        .is_some()
+    };
+    if let Some(user) = state.admin_service.authenticate(&credentials).await
         && state
             .admin_service
-            .change_password(&username, &new_password)
+            .change_password(&user.username, &new_password)
             .await
             .is_ok()
     {
@@ -65,12 +74,11 @@ async fn change_password(
 }
 
 async fn get_user(
-    AuthBasic((username, password)): AuthBasic,
+    credentials: Credentials,
     State(state): State<RoadworkServerData>,
 ) -> Result<Json<User>, StatusCode> {
-    info!("get_user username={}", username);
-    let password: String = password.unwrap_or_else(|| "".to_string());
-    if let Some(user) = state.admin_service.get_user(&username, &password).await {
+    info!("get_user username={}", credentials);
+    if let Some(user) = state.admin_service.authenticate(&credentials).await {
         let mut web_user = user.clone();
         web_user.password_hash = "?????".to_string();
         info!("get_user -> {:?}", web_user);
@@ -81,6 +89,47 @@ async fn get_user(
     }
 }
 
+/// Exchange the username and password for an access token, sent as a bearer token to the other routes
+async fn login(
+    credentials: Credentials,
+    State(state): State<RoadworkServerData>,
+) -> Result<Json<Token>, StatusCode> {
+    info!("login username={}", credentials);
+    state
+        .admin_service
+        .login(&credentials)
+        .await
+        .map(Json)
+        .ok_or(StatusCode::UNAUTHORIZED)
+}
+
+/// Exchange a refresh token for a new access token, the refresh token cannot be used again
+async fn refresh(
+    State(state): State<RoadworkServerData>,
+    Json(refresh_request): Json<RefreshRequest>,
+) -> Result<Json<Token>, StatusCode> {
+    info!("refresh");
+    state
+        .admin_service
+        .refresh(&refresh_request.refresh_token)
+        .await
+        .map(Json)
+        .ok_or(StatusCode::UNAUTHORIZED)
+}
+
+/// Revoke the access token, or every token of the user when called with the password
+async fn logout(credentials: Credentials, State(state): State<RoadworkServerData>) -> StatusCode {
+    info!("logout username={}", credentials);
+    match state.admin_service.logout(&credentials).await {
+        Ok(true) => StatusCode::NO_CONTENT,
+        Ok(false) => StatusCode::UNAUTHORIZED,
+        Err(err) => {
+            warn!("logout failed: {}", err);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
 async fn check(Path((bcrypted, password)): Path<(String, String)>) -> &'static str {
     info!("check XXXXXXX");
     let result = hash::check(bcrypted.as_str(), &password);
@@ -91,10 +140,15 @@ async fn check(Path((bcrypted, password)): Path<(String, String)>) -> &'static s
     }
 }
 
-async fn salt(password: String) -> String {
+async fn salt(State(state): State<RoadworkServerData>, password: String) -> String {
     info!("Salt XXXXXXX");
-    let salted_password = hash::salt(&password);
-    let response = format!("Bcrypt {} -> {}", password, salted_password);
+    let salted_password = state.admin_service.hash_password(&password);
+    let response = format!(
+        "{} {} -> {}",
+        HashAlgorithm::of(&salted_password),
+        password,
+        salted_password
+    );
     info!("Salt XXXXXXX -> {}", salted_password);
     response
 }
@@ -0,0 +1,64 @@
+use std::collections::HashMap;
+
+use log::info;
+use roadwork_sync_lib::sync_data::{Status, SyncData};
+
+use crate::error::DataError;
+use crate::service::data::{DataService, now};
+
+/// The username recorded in the history for the roadworks added from the opendata feeds
+const INGESTION_USER: &str = "system:ingestion";
+
+impl DataService {
+    /// Add the feed roadworks missing from a team dataset, returns how many were added.
+    /// The roadworks already known or deleted by the team are left untouched so the team statuses are kept
+    pub(crate) async fn ingest(
+        &self,
+        team: &str,
+        opendata_service: &str,
+        ids: &[String],
+    ) -> Result<usize, DataError> {
+        let _guard = self.lock(team, opendata_service).await;
+        let existing_sync_data_list = self.get_data(team, opendata_service).await?;
+        let tombstones = self
+            .data_repository
+            .load_tombstones(team, opendata_service, 0)
+            .await?;
+        let local_update_time = now();
+        let mut sync_data_list: HashMap<String, SyncData> = ids
+            .iter()
+            .filter(|id| {
+                !existing_sync_data_list.contains_key(*id) && !tombstones.contains_key(*id)
+            })
+            .map(|id| {
+                (
+                    id.clone(),
+                    SyncData {
+                        local_update_time,
+                        server_update_time: 0,
+                        status: Status::New,
+                        dirty: true,
+                    },
+                )
+            })
+            .collect();
+        info!(
+            "ingest team={} service={} new={}",
+            team,
+            opendata_service,
+            sync_data_list.len()
+        );
+        if sync_data_list.is_empty() {
+            return Ok(0);
+        }
+        self.merge_and_save(
+            team,
+            opendata_service,
+            INGESTION_USER,
+            &tombstones,
+            &mut sync_data_list,
+        )
+        .await?;
+        Ok(sync_data_list.len())
+    }
+}
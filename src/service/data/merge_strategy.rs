@@ -0,0 +1,84 @@
+use roadwork_sync_lib::sync_data::SyncData;
+
+/// Decide which version of an entry is kept when it was modified by the client
+/// and on the server since the last client sync
+pub(crate) trait MergeStrategy: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Returns true if the client version is kept, false if the server version is kept
+    fn client_wins(&self, client_sync_data: &SyncData, server_sync_data: &SyncData) -> bool;
+}
+
+/// The greatest status wins, the client wins if the statuses are equal
+pub(crate) struct GreatestStatus;
+
+impl MergeStrategy for GreatestStatus {
+    fn name(&self) -> &'static str {
+        "greatest_status"
+    }
+
+    fn client_wins(&self, client_sync_data: &SyncData, server_sync_data: &SyncData) -> bool {
+        client_sync_data.status >= server_sync_data.status
+    }
+}
+
+/// The server version is always kept
+pub(crate) struct ServerWins;
+
+impl MergeStrategy for ServerWins {
+    fn name(&self) -> &'static str {
+        "server_wins"
+    }
+
+    fn client_wins(&self, _: &SyncData, _: &SyncData) -> bool {
+        false
+    }
+}
+
+/// The client version is always kept
+pub(crate) struct ClientWins;
+
+impl MergeStrategy for ClientWins {
+    fn name(&self) -> &'static str {
+        "client_wins"
+    }
+
+    fn client_wins(&self, _: &SyncData, _: &SyncData) -> bool {
+        true
+    }
+}
+
+/// The most recent modification wins, the modification times of both versions are compared.
+/// The merge stamps the stored version with the server time, so the client clock is assumed
+/// to be set to the server clock
+pub(crate) struct LastWriterWins;
+
+impl MergeStrategy for LastWriterWins {
+    fn name(&self) -> &'static str {
+        "last_writer_wins"
+    }
+
+    fn client_wins(&self, client_sync_data: &SyncData, server_sync_data: &SyncData) -> bool {
+        client_sync_data.local_update_time >= server_sync_data.local_update_time
+    }
+}
+
+pub(crate) const DEFAULT_MERGE_STRATEGY: &dyn MergeStrategy = &GreatestStatus;
+
+const MERGE_STRATEGIES: [&dyn MergeStrategy; 4] =
+    [&GreatestStatus, &ServerWins, &ClientWins, &LastWriterWins];
+
+/// Find a merge strategy by its name
+pub(crate) fn find_merge_strategy(name: &str) -> Option<&'static dyn MergeStrategy> {
+    MERGE_STRATEGIES
+        .into_iter()
+        .find(|merge_strategy| merge_strategy.name() == name)
+}
+
+/// The names of the available merge strategies
+pub(crate) fn merge_strategy_names() -> Vec<&'static str> {
+    MERGE_STRATEGIES
+        .iter()
+        .map(|merge_strategy| merge_strategy.name())
+        .collect()
+}
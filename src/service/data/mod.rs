@@ -0,0 +1,751 @@
+mod ingestion;
+pub(crate) mod merge_strategy;
+pub(crate) mod opendata_service;
+mod quarantine;
+mod retention;
+mod snapshot;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use log::{info, warn};
+use roadwork_sync_lib::sync_data::SyncData;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{OwnedMutexGuard, broadcast, mpsc};
+
+use crate::config::Config;
+use crate::error::DataError;
+use crate::service::data::merge_strategy::{
+    DEFAULT_MERGE_STRATEGY, MergeStrategy, find_merge_strategy,
+};
+use crate::service::data_repository::{
+    Attribution, DataRepository, DatasetInfo, DatasetUpdate, StatusChange,
+};
+
+pub(crate) use retention::{ALL_SERVICES, MAX_RETENTION_DAYS};
+pub(crate) use snapshot::SnapshotDiff;
+
+pub(crate) const DAY_MILLIS: u64 = 24 * 60 * 60 * 1000;
+
+/// The status recorded in the history when an entry is deleted
+const DELETED_STATUS: &str = "deleted";
+
+/// The dataset locks, by (team, opendata service).
+/// A lock nobody holds or waits for is removed when the next one is taken
+type DatasetLocks = Arc<Mutex<HashMap<(String, String), Arc<tokio::sync::Mutex<()>>>>>;
+
+/// A delta sync request, the client sends only its dirty entries
+#[derive(Debug, Deserialize)]
+pub(crate) struct DeltaSyncRequest {
+    /// The cursor returned by the previous delta sync, 0 for a first sync
+    #[serde(default)]
+    pub(crate) since: u64,
+    pub(crate) entries: HashMap<String, SyncData>,
+    /// The ids deleted by the client
+    #[serde(default)]
+    pub(crate) deleted: Vec<String>,
+}
+
+/// The entries modified on the server since the client cursor
+#[derive(Debug, Serialize)]
+pub(crate) struct DeltaSyncResponse {
+    /// The cursor to send with the next delta sync
+    pub(crate) cursor: u64,
+    pub(crate) entries: HashMap<String, SyncData>,
+    /// The ids deleted since the client cursor
+    pub(crate) deleted: Vec<String>,
+    pub(crate) conflicts: ConflictReport,
+}
+
+/// The merged entries of a full sync with the conflicts found during the merge
+#[derive(Debug, Serialize)]
+pub(crate) struct SetDataResponse {
+    pub(crate) data: HashMap<String, SyncData>,
+    /// The deleted ids of the dataset, the client must remove them
+    pub(crate) deleted: Vec<String>,
+    pub(crate) conflicts: ConflictReport,
+}
+
+/// The result of a batch sync, by opendata service
+#[derive(Debug, Default, Serialize)]
+pub(crate) struct BatchSetDataResponse {
+    pub(crate) results: HashMap<String, SetDataResponse>,
+    pub(crate) errors: HashMap<String, BatchError>,
+}
+
+/// Why the sync of one opendata service of a batch failed
+#[derive(Debug, Serialize)]
+pub(crate) struct BatchError {
+    /// The HTTP status the single service sync would have returned
+    pub(crate) status: u16,
+    pub(crate) message: String,
+}
+
+/// The entries of a dataset with the last modifying user of each entry
+#[derive(Debug, Serialize)]
+pub(crate) struct AttributedData {
+    pub(crate) data: HashMap<String, SyncData>,
+    pub(crate) attribution: HashMap<String, Attribution>,
+}
+
+/// The entries modified both by the client and on the server since the last client sync
+#[derive(Debug, Default, Serialize)]
+pub(crate) struct ConflictReport {
+    /// The server version overrode the client modification, the client edit is discarded
+    pub(crate) server_won: Vec<Conflict>,
+    /// The client modification overrode a newer server change
+    pub(crate) client_won: Vec<Conflict>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct Conflict {
+    pub(crate) id: String,
+    pub(crate) client_status: String,
+    pub(crate) server_status: String,
+}
+
+impl Conflict {
+    fn new(id: &str, client_sync_data: &SyncData, server_sync_data: &SyncData) -> Self {
+        Conflict {
+            id: id.to_string(),
+            client_status: client_sync_data.status.to_string(),
+            server_status: server_sync_data.status.to_string(),
+        }
+    }
+}
+
+/// The entries of a dataset modified or deleted by a sync, pushed to the subscribed clients
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct DatasetChange {
+    pub(crate) team: String,
+    pub(crate) service: String,
+    /// The user whose sync produced the change
+    pub(crate) username: String,
+    pub(crate) entries: HashMap<String, SyncData>,
+    pub(crate) deleted: Vec<String>,
+    /// The status changes of the entries, for the webhooks
+    #[serde(skip)]
+    pub(crate) status_changes: Vec<StatusChange>,
+}
+
+/// How many changes a slow subscriber may lag behind before missing changes
+const CHANGE_CHANNEL_CAPACITY: usize = 1024;
+
+#[derive(Clone)]
+pub(crate) struct DataService {
+    data_repository: DataRepository,
+    /// One lock per (team, opendata service) so concurrent syncs of the same dataset are serialized
+    locks: DatasetLocks,
+    changes: broadcast::Sender<DatasetChange>,
+    /// The changes with status changes, for the webhooks which must not miss any
+    webhook_changes: mpsc::UnboundedSender<DatasetChange>,
+    config: Config,
+}
+
+impl DataService {
+    pub(crate) fn new(
+        data_repository: DataRepository,
+        config: Config,
+        webhook_changes: mpsc::UnboundedSender<DatasetChange>,
+    ) -> Self {
+        let (changes, _) = broadcast::channel(CHANGE_CHANNEL_CAPACITY);
+        DataService {
+            data_repository,
+            locks: Arc::new(Mutex::new(HashMap::new())),
+            changes,
+            webhook_changes,
+            config,
+        }
+    }
+
+    /// Receive the changes of every dataset, the receiver filters the datasets it is interested in
+    pub(crate) fn subscribe(&self) -> broadcast::Receiver<DatasetChange> {
+        self.changes.subscribe()
+    }
+
+    /// Push a change to the subscribers, if any
+    fn publish(
+        &self,
+        team: &str,
+        opendata_service: &str,
+        username: &str,
+        entries: HashMap<String, SyncData>,
+        deleted: Vec<String>,
+        status_changes: Vec<StatusChange>,
+    ) {
+        if entries.is_empty() && deleted.is_empty() {
+            return;
+        }
+        let change = DatasetChange {
+            team: team.to_string(),
+            service: opendata_service.to_string(),
+            username: username.to_string(),
+            entries,
+            deleted,
+            status_changes,
+        };
+        if !change.status_changes.is_empty() && self.webhook_changes.send(change.clone()).is_err() {
+            warn!("The webhooks stopped, the change is not notified");
+        }
+        // an error only means there is no subscriber
+        let _ = self.changes.send(change);
+    }
+
+    pub(crate) async fn set_data(
+        &self,
+        team: &str,
+        opendata_service: &str,
+        username: &str,
+        mut sync_data_list: HashMap<String, SyncData>,
+    ) -> Result<SetDataResponse, DataError> {
+        info!("set_data");
+        let _guard = self.lock(team, opendata_service).await;
+        let tombstones = self
+            .data_repository
+            .load_tombstones(team, opendata_service, 0)
+            .await?;
+        let (_, conflicts) = self
+            .merge_and_save(
+                team,
+                opendata_service,
+                username,
+                &tombstones,
+                &mut sync_data_list,
+            )
+            .await?;
+        Ok(SetDataResponse {
+            data: sync_data_list,
+            deleted: tombstones.into_keys().collect(),
+            conflicts,
+        })
+    }
+
+    /// Merge the dirty entries of the client and return every entry modified since the client cursor
+    pub(crate) async fn sync_delta(
+        &self,
+        team: &str,
+        opendata_service: &str,
+        username: &str,
+        delta_sync_request: DeltaSyncRequest,
+    ) -> Result<DeltaSyncResponse, DataError> {
+        info!("sync_delta since={}", delta_sync_request.since);
+        let _guard = self.lock(team, opendata_service).await;
+        let since = delta_sync_request.since;
+        let mut sync_data_list = delta_sync_request.entries;
+        if !delta_sync_request.deleted.is_empty() {
+            self.delete_entries(
+                team,
+                opendata_service,
+                username,
+                &delta_sync_request.deleted,
+                false,
+            )
+            .await?;
+        }
+        let tombstones = self
+            .data_repository
+            .load_tombstones(team, opendata_service, 0)
+            .await?;
+        let (server_update_time, conflicts) = self
+            .merge_and_save(
+                team,
+                opendata_service,
+                username,
+                &tombstones,
+                &mut sync_data_list,
+            )
+            .await?;
+        let entries = self
+            .data_repository
+            .load_since(team, opendata_service, since)
+            .await?;
+        let deleted = tombstones
+            .into_iter()
+            .filter(|(_, deletion_time)| *deletion_time >= since)
+            .map(|(id, _)| id)
+            .collect();
+        Ok(DeltaSyncResponse {
+            // entries stamped later in the same millisecond must not be missed, so the cursor is inclusive
+            cursor: server_update_time.max(since),
+            entries,
+            deleted,
+            conflicts,
+        })
+    }
+
+    /// Delete entries of a dataset, the deletion is propagated to the other clients by tombstones
+    pub(crate) async fn delete(
+        &self,
+        team: &str,
+        opendata_service: &str,
+        username: &str,
+        ids: &[String],
+    ) -> Result<(), DataError> {
+        info!("delete ids={:?}", ids);
+        let _guard = self.lock(team, opendata_service).await;
+        self.delete_entries(team, opendata_service, username, ids, false)
+            .await
+    }
+
+    /// Remove the tombstones older than the retention, in milliseconds
+    pub(crate) async fn purge_tombstones(&self, retention: u64) -> Result<u64, String> {
+        self.data_repository
+            .purge_tombstones(now().saturating_sub(retention))
+            .await
+    }
+
+    /// Archive the entries if asked, in the same transaction as their deletion.
+    /// The dataset lock must be held by the caller
+    async fn delete_entries(
+        &self,
+        team: &str,
+        opendata_service: &str,
+        username: &str,
+        ids: &[String],
+        archive: bool,
+    ) -> Result<(), DataError> {
+        let existing_sync_data_list = self.get_data(team, opendata_service).await?;
+        let archived_sync_data_list: HashMap<String, SyncData> = if archive {
+            ids.iter()
+                .filter_map(|id| {
+                    existing_sync_data_list
+                        .get(id)
+                        .map(|sync_data| (id.clone(), sync_data.clone()))
+                })
+                .collect()
+        } else {
+            HashMap::new()
+        };
+        let deletion_time = now();
+        let status_changes = deletion_status_changes(
+            team,
+            opendata_service,
+            username,
+            deletion_time,
+            &existing_sync_data_list,
+            ids,
+        );
+        self.data_repository
+            .delete(
+                team,
+                opendata_service,
+                ids,
+                deletion_time,
+                &status_changes,
+                &archived_sync_data_list,
+            )
+            .await?;
+        self.publish(
+            team,
+            opendata_service,
+            username,
+            HashMap::new(),
+            ids.to_vec(),
+            status_changes,
+        );
+        Ok(())
+    }
+
+    /// Merge the client entries with the stored dataset and save the changed entries.
+    /// The deleted entries are removed from the client entries so they are not created again.
+    /// The dataset lock must be held by the caller.
+    /// Returns the server time given to the modified entries and the conflicts
+    async fn merge_and_save(
+        &self,
+        team: &str,
+        opendata_service: &str,
+        username: &str,
+        tombstones: &HashMap<String, u64>,
+        sync_data_list: &mut HashMap<String, SyncData>,
+    ) -> Result<(u64, ConflictReport), DataError> {
+        sync_data_list.retain(|id, _| !tombstones.contains_key(id));
+        let existing_sync_data_list = self.get_data(team, opendata_service).await?;
+        let merge_strategy = self.get_merge_strategy(opendata_service).await?;
+        let server_update_time = now();
+        let conflicts = merge(
+            &existing_sync_data_list,
+            sync_data_list,
+            server_update_time,
+            merge_strategy,
+        );
+        let changed_sync_data_list = changed_entries(&existing_sync_data_list, sync_data_list);
+        let status_changes = status_changes(
+            team,
+            opendata_service,
+            username,
+            server_update_time,
+            &existing_sync_data_list,
+            &changed_sync_data_list,
+        );
+        // the snapshot is the stored dataset with the changed entries
+        let snapshot = (!changed_sync_data_list.is_empty()).then(|| {
+            let mut snapshot_sync_data_list = existing_sync_data_list.clone();
+            snapshot_sync_data_list.extend(changed_sync_data_list.clone());
+            self.new_snapshot(username, server_update_time, snapshot_sync_data_list)
+        });
+        let update = DatasetUpdate {
+            sync_data_list: changed_sync_data_list,
+            status_changes,
+            snapshot,
+            attribution: Some((username.to_string(), server_update_time)),
+            last_sync: Some((username.to_string(), server_update_time)),
+            ..DatasetUpdate::default()
+        };
+        self.data_repository
+            .save(team, opendata_service, &update)
+            .await?;
+        self.publish(
+            team,
+            opendata_service,
+            username,
+            update.sync_data_list,
+            Vec::new(),
+            update.status_changes,
+        );
+        Ok((server_update_time, conflicts))
+    }
+
+    /// The merge strategy configured for the opendata service, or the default one
+    async fn get_merge_strategy(
+        &self,
+        opendata_service: &str,
+    ) -> Result<&'static dyn MergeStrategy, String> {
+        let merge_strategy = match self
+            .data_repository
+            .find_merge_strategy(opendata_service)
+            .await?
+        {
+            Some(name) => find_merge_strategy(&name).unwrap_or_else(|| {
+                warn!("Unknown merge strategy {} for {}", name, opendata_service);
+                DEFAULT_MERGE_STRATEGY
+            }),
+            None => DEFAULT_MERGE_STRATEGY,
+        };
+        Ok(merge_strategy)
+    }
+
+    /// The merge strategy name by opendata service, services not listed use the default strategy
+    pub(crate) async fn list_merge_strategies(&self) -> Result<HashMap<String, String>, String> {
+        self.data_repository.list_merge_strategies().await
+    }
+
+    pub(crate) async fn set_merge_strategy(
+        &self,
+        opendata_service: &str,
+        name: &str,
+    ) -> Result<(), String> {
+        if find_merge_strategy(name).is_none() {
+            return Err(format!("Unknown merge strategy {}", name));
+        }
+        self.data_repository
+            .set_merge_strategy(opendata_service, name)
+            .await
+    }
+
+    pub(crate) async fn delete_merge_strategy(&self, opendata_service: &str) -> Result<(), String> {
+        self.data_repository
+            .delete_merge_strategy(opendata_service)
+            .await
+    }
+
+    /// The entries of a dataset, refused if the dataset is quarantined
+    pub(crate) async fn get_data(
+        &self,
+        team: &str,
+        opendata_service: &str,
+    ) -> Result<HashMap<String, SyncData>, DataError> {
+        info!("getData team={} service={}", team, opendata_service);
+        let sync_data_list = self.data_repository.load(team, opendata_service).await?;
+        if self
+            .data_repository
+            .is_quarantined(team, opendata_service)
+            .await?
+        {
+            return Err(DataError::Quarantined(
+                team.to_string(),
+                opendata_service.to_string(),
+            ));
+        }
+        Ok(sync_data_list)
+    }
+
+    /// The last modifying user of each entry of a dataset, entries modified before the attribution
+    /// was recorded have none
+    pub(crate) async fn get_attribution(
+        &self,
+        team: &str,
+        opendata_service: &str,
+    ) -> Result<HashMap<String, Attribution>, String> {
+        self.data_repository
+            .load_attribution(team, opendata_service)
+            .await
+    }
+
+    /// The opendata services a team has data for
+    pub(crate) async fn list_datasets(&self, team: &str) -> Result<Vec<DatasetInfo>, String> {
+        self.data_repository.list_team_datasets(team).await
+    }
+
+    /// The status changes of a roadwork entry, oldest first
+    pub(crate) async fn get_history(
+        &self,
+        team: &str,
+        opendata_service: &str,
+        id: &str,
+    ) -> Result<Vec<StatusChange>, String> {
+        self.data_repository
+            .load_history(team, opendata_service, id)
+            .await
+    }
+
+    /// Wait for the lock of the dataset, it is released when the guard is dropped
+    async fn lock(&self, team: &str, opendata_service: &str) -> OwnedMutexGuard<()> {
+        let lock = {
+            let mut locks = self.locks.lock().unwrap();
+            locks.retain(|_, lock| Arc::strong_count(lock) > 1);
+            locks
+                .entry((team.to_string(), opendata_service.to_string()))
+                .or_default()
+                .clone()
+        };
+        lock.lock_owned().await
+    }
+}
+
+/// Keep only the merged entries that are new or differ from the stored version
+fn changed_entries(
+    existing_sync_data_list: &HashMap<String, SyncData>,
+    sync_data_list: &HashMap<String, SyncData>,
+) -> HashMap<String, SyncData> {
+    sync_data_list
+        .iter()
+        .filter(|(id, sync_data)| {
+            existing_sync_data_list
+                .get(*id)
+                .is_none_or(|existing_sync_data| {
+                    existing_sync_data.server_update_time != sync_data.server_update_time
+                        || existing_sync_data.status != sync_data.status
+                })
+        })
+        .map(|(id, sync_data)| (id.clone(), sync_data.clone()))
+        .collect()
+}
+
+/// The status changes of the deleted entries that exist in the stored entries
+fn deletion_status_changes(
+    team: &str,
+    opendata_service: &str,
+    username: &str,
+    deletion_time: u64,
+    existing_sync_data_list: &HashMap<String, SyncData>,
+    ids: &[String],
+) -> Vec<StatusChange> {
+    ids.iter()
+        .filter_map(|id| {
+            existing_sync_data_list
+                .get(id)
+                .map(|existing_sync_data| StatusChange {
+                    id: id.clone(),
+                    old_status: Some(existing_sync_data.status.to_string()),
+                    new_status: DELETED_STATUS.to_string(),
+                    time: deletion_time,
+                    team: team.to_string(),
+                    service: opendata_service.to_string(),
+                    username: username.to_string(),
+                })
+        })
+        .collect()
+}
+
+/// The status changes between the stored entries and the changed entries
+fn status_changes(
+    team: &str,
+    opendata_service: &str,
+    username: &str,
+    server_update_time: u64,
+    existing_sync_data_list: &HashMap<String, SyncData>,
+    changed_sync_data_list: &HashMap<String, SyncData>,
+) -> Vec<StatusChange> {
+    changed_sync_data_list
+        .iter()
+        .filter_map(|(id, sync_data)| {
+            let old_status = existing_sync_data_list
+                .get(id)
+                .map(|existing_sync_data| &existing_sync_data.status);
+            if old_status == Some(&sync_data.status) {
+                return None;
+            }
+            Some(StatusChange {
+                id: id.clone(),
+                old_status: old_status.map(|status| status.to_string()),
+                new_status: sync_data.status.to_string(),
+                time: server_update_time,
+                team: team.to_string(),
+                service: opendata_service.to_string(),
+                username: username.to_string(),
+            })
+        })
+        .collect()
+}
+
+/**
+ * Merge existing data with new data.
+ *
+ * @param existing_sync_data_list        the existing data
+ * @param new_sync_data_list the new data
+ * @param server_update_time the server time given to the modified entries
+ * @param merge_strategy decides which version is kept when both the client and the server modified an entry
+ * @return the conflicts between the client modifications and the server changes
+ */
+fn merge(
+    existing_sync_data_list: &HashMap<String, SyncData>,
+    new_sync_data_list: &mut HashMap<String, SyncData>,
+    server_update_time: u64,
+    merge_strategy: &dyn MergeStrategy,
+) -> ConflictReport {
+    info!("merge");
+    let mut conflicts = ConflictReport::default();
+    for (id, existing_sync_data) in existing_sync_data_list {
+        if let Some(new_sync_data) = new_sync_data_list.get_mut(id) {
+            if new_sync_data.dirty {
+                if new_sync_data.server_update_time == existing_sync_data.server_update_time {
+                    info!("{} dirty=true server time is identical, update time", id);
+                    new_sync_data.update_time(server_update_time);
+                } else {
+                    // server version is more up to date, but it is also modified by the client, the merge strategy decides
+                    // both versions having the same status is not a conflict, whichever is kept
+                    let conflicting = new_sync_data.status != existing_sync_data.status;
+                    if merge_strategy.client_wins(new_sync_data, existing_sync_data) {
+                        info!(
+                            "{} dirty=true server time is modified, {} keeps the client version ({} over {})",
+                            id,
+                            merge_strategy.name(),
+                            new_sync_data.status,
+                            existing_sync_data.status
+                        );
+                        if conflicting {
+                            conflicts.client_won.push(Conflict::new(
+                                id,
+                                new_sync_data,
+                                existing_sync_data,
+                            ));
+                        }
+                        new_sync_data.update_time(server_update_time);
+                    } else {
+                        info!(
+                            "{} dirty=true server time is modified, {} keeps the server version ({} over {})",
+                            id,
+                            merge_strategy.name(),
+                            existing_sync_data.status,
+                            new_sync_data.status
+                        );
+                        if conflicting {
+                            conflicts.server_won.push(Conflict::new(
+                                id,
+                                new_sync_data,
+                                existing_sync_data,
+                            ));
+                        }
+                        new_sync_data.copy(existing_sync_data);
+                    }
+                }
+                new_sync_data.dirty = false;
+            } else if new_sync_data.server_update_time != existing_sync_data.server_update_time {
+                info!(
+                    "{} dirty=false server time is different, copying server version",
+                    id
+                );
+                new_sync_data.copy(existing_sync_data);
+            }
+        }
+    }
+    new_sync_data_list
+        .iter_mut()
+        .filter(|(id, _)| !existing_sync_data_list.contains_key(*id))
+        .for_each(|(id, sync_data)| {
+            info!("{} is new, update time", id);
+            sync_data.update_time(server_update_time);
+        });
+    new_sync_data_list
+        .iter_mut()
+        .for_each(|(_, sync_data)| sync_data.dirty = false);
+    conflicts
+}
+
+pub(crate) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use roadwork_sync_lib::sync_data::Status;
+
+    use super::*;
+    use crate::service::data::merge_strategy::{GreatestStatus, LastWriterWins};
+
+    fn sync_data(local_update_time: u64, server_update_time: u64, status: Status) -> SyncData {
+        SyncData {
+            local_update_time,
+            server_update_time,
+            status,
+            dirty: false,
+        }
+    }
+
+    /// The client modified the entry at 30 after its last sync at 10, the server version changed at 20
+    fn merge_modified_entry(
+        client_status: Status,
+        server_status: Status,
+        merge_strategy: &dyn MergeStrategy,
+    ) -> (SyncData, ConflictReport) {
+        let existing_sync_data_list =
+            HashMap::from([("1".to_string(), sync_data(20, 20, server_status))]);
+        let mut client_sync_data = sync_data(30, 10, client_status);
+        client_sync_data.dirty = true;
+        let mut sync_data_list = HashMap::from([("1".to_string(), client_sync_data)]);
+        let conflicts = merge(
+            &existing_sync_data_list,
+            &mut sync_data_list,
+            100,
+            merge_strategy,
+        );
+        (sync_data_list.remove("1").unwrap(), conflicts)
+    }
+
+    #[test]
+    fn greatest_status_keeps_the_greatest_status() {
+        let (merged, conflicts) =
+            merge_modified_entry(Status::New, Status::Finished, &GreatestStatus);
+        assert_eq!(merged.status, Status::Finished);
+        assert_eq!(merged.server_update_time, 20);
+        assert_eq!(conflicts.server_won.len(), 1);
+        assert!(conflicts.client_won.is_empty());
+
+        let (merged, conflicts) =
+            merge_modified_entry(Status::Finished, Status::New, &GreatestStatus);
+        assert_eq!(merged.status, Status::Finished);
+        assert_eq!(merged.server_update_time, 100);
+        assert_eq!(conflicts.client_won.len(), 1);
+    }
+
+    #[test]
+    fn equal_statuses_are_not_a_conflict() {
+        let (merged, conflicts) =
+            merge_modified_entry(Status::Ongoing, Status::Ongoing, &GreatestStatus);
+        assert_eq!(merged.status, Status::Ongoing);
+        assert!(conflicts.client_won.is_empty());
+        assert!(conflicts.server_won.is_empty());
+    }
+
+    #[test]
+    fn last_writer_wins_compares_modification_times() {
+        let (merged, conflicts) =
+            merge_modified_entry(Status::New, Status::Finished, &LastWriterWins);
+        assert_eq!(merged.status, Status::New);
+        assert_eq!(conflicts.client_won.len(), 1);
+    }
+}
@@ -0,0 +1,45 @@
+use crate::service::data::DataService;
+use crate::service::data_repository::OpendataService;
+
+const MAX_OPENDATA_SERVICE_NAME_LENGTH: usize = 64;
+
+/// An opendata service name is made of ascii letters, digits, '-' and '_'
+pub(crate) fn is_valid_opendata_service_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_OPENDATA_SERVICE_NAME_LENGTH
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+impl DataService {
+    pub(crate) async fn list_opendata_services(&self) -> Result<Vec<OpendataService>, String> {
+        self.data_repository.list_opendata_services().await
+    }
+
+    pub(crate) async fn find_opendata_service(
+        &self,
+        id: &str,
+    ) -> Result<Option<OpendataService>, String> {
+        self.data_repository.find_opendata_service(id).await
+    }
+
+    pub(crate) async fn save_opendata_service(
+        &self,
+        opendata_service: &OpendataService,
+    ) -> Result<(), String> {
+        if !is_valid_opendata_service_name(&opendata_service.id) {
+            return Err(format!(
+                "Malformed opendata service name {}",
+                opendata_service.id
+            ));
+        }
+        self.data_repository
+            .save_opendata_service(opendata_service)
+            .await
+    }
+
+    pub(crate) async fn delete_opendata_service(&self, id: &str) -> Result<(), String> {
+        self.data_repository.delete_opendata_service(id).await
+    }
+}
@@ -0,0 +1,78 @@
+use log::info;
+
+use crate::service::data::DataService;
+use crate::service::data_repository::{QuarantinedDataset, QuarantinedEntry};
+
+impl DataService {
+    pub(crate) async fn list_quarantined(&self) -> Result<Vec<QuarantinedDataset>, String> {
+        self.data_repository.list_quarantined().await
+    }
+
+    /// Accept the dataset as it is: the corrupt entries stay out of it and the syncs are allowed
+    /// again, their raw data is kept until purged
+    pub(crate) async fn repair_quarantined(
+        &self,
+        team: &str,
+        opendata_service: &str,
+    ) -> Result<(), String> {
+        info!(
+            "repair_quarantined team={} service={}",
+            team, opendata_service
+        );
+        let _guard = self.lock(team, opendata_service).await;
+        self.data_repository
+            .release_quarantine(team, opendata_service)
+            .await
+    }
+
+    /// Roll a quarantined dataset back to its latest snapshot and allow the syncs again,
+    /// the quarantine is lifted in the transaction restoring the dataset.
+    /// Returns None if the dataset has no snapshot, it stays quarantined then
+    pub(crate) async fn restore_quarantined(
+        &self,
+        team: &str,
+        opendata_service: &str,
+        username: &str,
+    ) -> Result<Option<i64>, String> {
+        info!(
+            "restore_quarantined team={} service={}",
+            team, opendata_service
+        );
+        let _guard = self.lock(team, opendata_service).await;
+        let Some(snapshot) = self
+            .data_repository
+            .list_snapshots(team, opendata_service)
+            .await?
+            .into_iter()
+            .next()
+        else {
+            return Ok(None);
+        };
+        Ok(self
+            .apply_snapshot(team, opendata_service, snapshot.id, username, true)
+            .await?
+            .map(|_| snapshot.id))
+    }
+
+    /// The raw data of the corrupt entries of a dataset
+    pub(crate) async fn list_quarantined_entries(
+        &self,
+        team: &str,
+        opendata_service: &str,
+    ) -> Result<Vec<QuarantinedEntry>, String> {
+        self.data_repository
+            .list_quarantined_entries(team, opendata_service)
+            .await
+    }
+
+    /// Forget the raw data of the corrupt entries of a dataset, returns how many were removed
+    pub(crate) async fn purge_quarantined_entries(
+        &self,
+        team: &str,
+        opendata_service: &str,
+    ) -> Result<u64, String> {
+        self.data_repository
+            .purge_quarantined_entries(team, opendata_service)
+            .await
+    }
+}
@@ -0,0 +1,114 @@
+use std::collections::HashMap;
+
+use log::{info, warn};
+
+use crate::service::data::{DAY_MILLIS, DataService, now};
+use crate::service::data_repository::{RetentionPolicy, StaleEntry};
+
+/// The username recorded in the history for the entries removed by the retention
+const RETENTION_USER: &str = "system:retention";
+
+/// The service of a policy applying to every service of a team
+pub(crate) const ALL_SERVICES: &str = "*";
+
+/// The longest retention of a policy, a hundred years
+pub(crate) const MAX_RETENTION_DAYS: u64 = 36500;
+
+impl DataService {
+    pub(crate) async fn list_retention_policies(&self) -> Result<Vec<RetentionPolicy>, String> {
+        self.data_repository.list_retention_policies().await
+    }
+
+    pub(crate) async fn set_retention_policy(
+        &self,
+        team: &str,
+        opendata_service: &str,
+        retention_days: u64,
+    ) -> Result<(), String> {
+        self.data_repository
+            .set_retention_policy(team, opendata_service, retention_days)
+            .await
+    }
+
+    pub(crate) async fn delete_retention_policy(
+        &self,
+        team: &str,
+        opendata_service: &str,
+    ) -> Result<(), String> {
+        self.data_repository
+            .delete_retention_policy(team, opendata_service)
+            .await
+    }
+
+    /// The entries older than the retention of their dataset, datasets without policy are kept forever
+    pub(crate) async fn find_stale_entries(&self) -> Result<Vec<StaleEntry>, String> {
+        let mut stale_entries = Vec::new();
+        for (team, service, before) in self.find_retention_limits().await? {
+            stale_entries.extend(
+                self.data_repository
+                    .find_stale_entries(&team, &service, before)
+                    .await?,
+            );
+        }
+        Ok(stale_entries)
+    }
+
+    /// Archive and delete the stale entries, returns the number of removed entries.
+    /// The stale entries are found again under the dataset lock, a sync may have updated them
+    pub(crate) async fn purge_stale_entries(&self) -> Result<usize, String> {
+        let mut count = 0;
+        for (team, service, before) in self.find_retention_limits().await? {
+            let _guard = self.lock(&team, &service).await;
+            if self.data_repository.is_quarantined(&team, &service).await? {
+                warn!("purge_stale_entries skips quarantined {}/{}", team, service);
+                continue;
+            }
+            let ids: Vec<String> = self
+                .data_repository
+                .find_stale_entries(&team, &service, before)
+                .await?
+                .into_iter()
+                .map(|stale_entry| stale_entry.id)
+                .collect();
+            if ids.is_empty() {
+                continue;
+            }
+            info!(
+                "purge_stale_entries team={} service={} count={}",
+                team,
+                service,
+                ids.len()
+            );
+            self.delete_entries(&team, &service, RETENTION_USER, &ids, true)
+                .await?;
+            count += ids.len();
+        }
+        Ok(count)
+    }
+
+    /// The datasets having a retention policy, with the server time before which their entries are stale
+    async fn find_retention_limits(&self) -> Result<Vec<(String, String, u64)>, String> {
+        let policies: HashMap<(String, String), u64> = self
+            .data_repository
+            .list_retention_policies()
+            .await?
+            .into_iter()
+            .map(|policy| ((policy.team, policy.service), policy.retention_days))
+            .collect();
+        let now = now();
+        let mut retention_limits = Vec::new();
+        for (team, service) in self.data_repository.list_datasets().await? {
+            let retention_days = policies
+                .get(&(team.clone(), service.clone()))
+                .or_else(|| policies.get(&(team.clone(), ALL_SERVICES.to_string())));
+            if let Some(retention_days) = retention_days {
+                // a retention too long to be computed keeps everything
+                let before = retention_days
+                    .checked_mul(DAY_MILLIS)
+                    .map_or(0, |retention| now.saturating_sub(retention));
+                retention_limits.push((team, service, before));
+            }
+        }
+        Ok(retention_limits)
+    }
+}
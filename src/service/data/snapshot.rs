@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+
+use log::info;
+use roadwork_sync_lib::sync_data::SyncData;
+use serde::Serialize;
+
+use crate::service::data::{DAY_MILLIS, DataService, deletion_status_changes, now, status_changes};
+use crate::service::data_repository::{DatasetUpdate, NewSnapshot, SnapshotInfo};
+
+/// The differences between two snapshots of a dataset
+#[derive(Debug, Default, Serialize)]
+pub(crate) struct SnapshotDiff {
+    /// The ids only in the newer snapshot
+    pub(crate) added: Vec<String>,
+    /// The ids only in the older snapshot
+    pub(crate) removed: Vec<String>,
+    pub(crate) changed: Vec<StatusDiff>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct StatusDiff {
+    pub(crate) id: String,
+    pub(crate) from_status: String,
+    pub(crate) to_status: String,
+}
+
+impl DataService {
+    /// The snapshot of a dataset version, saved with the change producing it
+    pub(super) fn new_snapshot(
+        &self,
+        username: &str,
+        time: u64,
+        sync_data_list: HashMap<String, SyncData>,
+    ) -> NewSnapshot {
+        NewSnapshot {
+            username: username.to_string(),
+            time,
+            sync_data_list,
+            max_count: self.config.snapshot_max_count,
+            min_time: time
+                .saturating_sub(self.config.snapshot_max_age_days.saturating_mul(DAY_MILLIS)),
+        }
+    }
+
+    /// The snapshots of a dataset, newest first
+    pub(crate) async fn list_snapshots(
+        &self,
+        team: &str,
+        opendata_service: &str,
+    ) -> Result<Vec<SnapshotInfo>, String> {
+        self.data_repository
+            .list_snapshots(team, opendata_service)
+            .await
+    }
+
+    /// The differences from a snapshot to another one, None if one of them does not exist
+    pub(crate) async fn diff_snapshots(
+        &self,
+        team: &str,
+        opendata_service: &str,
+        from: i64,
+        to: i64,
+    ) -> Result<Option<SnapshotDiff>, String> {
+        let from_sync_data_list = self
+            .data_repository
+            .load_snapshot(team, opendata_service, from)
+            .await?;
+        let to_sync_data_list = self
+            .data_repository
+            .load_snapshot(team, opendata_service, to)
+            .await?;
+        let (Some(from_sync_data_list), Some(to_sync_data_list)) =
+            (from_sync_data_list, to_sync_data_list)
+        else {
+            return Ok(None);
+        };
+        let mut diff = SnapshotDiff::default();
+        for (id, to_sync_data) in &to_sync_data_list {
+            match from_sync_data_list.get(id) {
+                None => diff.added.push(id.clone()),
+                Some(from_sync_data) if from_sync_data.status != to_sync_data.status => {
+                    diff.changed.push(StatusDiff {
+                        id: id.clone(),
+                        from_status: from_sync_data.status.to_string(),
+                        to_status: to_sync_data.status.to_string(),
+                    })
+                }
+                Some(_) => {}
+            }
+        }
+        diff.removed = from_sync_data_list
+            .keys()
+            .filter(|id| !to_sync_data_list.contains_key(*id))
+            .cloned()
+            .collect();
+        diff.added.sort();
+        diff.removed.sort();
+        diff.changed.sort_by(|diff1, diff2| diff1.id.cmp(&diff2.id));
+        Ok(Some(diff))
+    }
+
+    /// Roll the live dataset back to a snapshot, None if the snapshot does not exist.
+    /// The restored entries get a new server time so the clients take them,
+    /// the entries missing from the snapshot are deleted
+    pub(crate) async fn restore_snapshot(
+        &self,
+        team: &str,
+        opendata_service: &str,
+        id: i64,
+        username: &str,
+    ) -> Result<Option<()>, String> {
+        info!(
+            "restore_snapshot team={} service={} id={}",
+            team, opendata_service, id
+        );
+        let _guard = self.lock(team, opendata_service).await;
+        self.apply_snapshot(team, opendata_service, id, username, false)
+            .await
+    }
+
+    /// Roll the live dataset back to a snapshot, the caller holds the dataset lock.
+    /// A quarantined dataset is refused unless its quarantine is released with the restore
+    pub(super) async fn apply_snapshot(
+        &self,
+        team: &str,
+        opendata_service: &str,
+        id: i64,
+        username: &str,
+        release_quarantine: bool,
+    ) -> Result<Option<()>, String> {
+        let Some(mut sync_data_list) = self
+            .data_repository
+            .load_snapshot(team, opendata_service, id)
+            .await?
+        else {
+            return Ok(None);
+        };
+        let existing_sync_data_list = if release_quarantine {
+            self.data_repository.load(team, opendata_service).await?
+        } else {
+            self.get_data(team, opendata_service).await?
+        };
+        let removed_ids: Vec<String> = existing_sync_data_list
+            .keys()
+            .filter(|id| !sync_data_list.contains_key(*id))
+            .cloned()
+            .collect();
+        let restore_time = now();
+        sync_data_list
+            .values_mut()
+            .for_each(|sync_data| sync_data.update_time(restore_time));
+        let mut restore_status_changes = deletion_status_changes(
+            team,
+            opendata_service,
+            username,
+            restore_time,
+            &existing_sync_data_list,
+            &removed_ids,
+        );
+        restore_status_changes.extend(status_changes(
+            team,
+            opendata_service,
+            username,
+            restore_time,
+            &existing_sync_data_list,
+            &sync_data_list,
+        ));
+        let update = DatasetUpdate {
+            snapshot: Some(self.new_snapshot(username, restore_time, sync_data_list.clone())),
+            sync_data_list,
+            deleted_ids: removed_ids,
+            deletion_time: restore_time,
+            status_changes: restore_status_changes,
+            attribution: Some((username.to_string(), restore_time)),
+            release_quarantine,
+            ..DatasetUpdate::default()
+        };
+        self.data_repository
+            .save(team, opendata_service, &update)
+            .await?;
+        self.publish(
+            team,
+            opendata_service,
+            username,
+            update.sync_data_list,
+            update.deleted_ids,
+            update.status_changes,
+        );
+        Ok(Some(()))
+    }
+}
@@ -0,0 +1,87 @@
+use std::collections::HashMap;
+
+use log::info;
+use serde::Serialize;
+use sqlx::{Row, Sqlite, Transaction};
+
+use crate::service::data_repository::DataRepository;
+
+/// Who last modified a roadwork entry, always set by the server from the authenticated user
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct Attribution {
+    pub(crate) username: String,
+    pub(crate) team: String,
+    pub(crate) time: u64,
+}
+
+/// Record the user who modified the given entries, in the transaction saving them
+pub(super) async fn insert_attribution(
+    transaction: &mut Transaction<'_, Sqlite>,
+    team: &str,
+    service: &str,
+    ids: &[&String],
+    username: &str,
+    time: u64,
+) -> Result<(), String> {
+    info!(
+        "insert_attribution team={} service={} user={} count={}",
+        team,
+        service,
+        username,
+        ids.len()
+    );
+    let query = "INSERT OR REPLACE INTO sync_data_attribution (team, service, id, username, time) VALUES (?, ?, ?, ?, ?)";
+    for id in ids {
+        sqlx::query(query)
+            .bind(team)
+            .bind(service)
+            .bind(id)
+            .bind(username)
+            .bind(time as i64)
+            .execute(&mut **transaction)
+            .await
+            .map_err(|err| {
+                format!(
+                    "Error recording attribution for {}/{}: {}",
+                    team, service, err
+                )
+            })?;
+    }
+    Ok(())
+}
+
+impl DataRepository {
+    /// The last modifying user of the entries of a dataset, by id
+    pub(crate) async fn load_attribution(
+        &self,
+        team: &str,
+        service: &str,
+    ) -> Result<HashMap<String, Attribution>, String> {
+        let query =
+            "SELECT id, username, time FROM sync_data_attribution WHERE team = ? AND service = ?";
+        let rows = sqlx::query(query)
+            .bind(team)
+            .bind(service)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|err| {
+                format!(
+                    "Error loading attribution for {}/{}: {}",
+                    team, service, err
+                )
+            })?;
+        Ok(rows
+            .iter()
+            .map(|row| {
+                (
+                    row.get(0),
+                    Attribution {
+                        username: row.get(1),
+                        team: team.to_string(),
+                        time: row.get::<i64, _>(2) as u64,
+                    },
+                )
+            })
+            .collect())
+    }
+}
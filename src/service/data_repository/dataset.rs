@@ -0,0 +1,82 @@
+use std::collections::BTreeMap;
+
+use log::info;
+use serde::Serialize;
+use sqlx::{Row, Sqlite, Transaction};
+
+use crate::service::data_repository::DataRepository;
+
+/// An opendata service dataset of a team
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct DatasetInfo {
+    pub(crate) service: String,
+    pub(crate) entries: u64,
+    /// Missing if the dataset was never synced since the sync were recorded
+    pub(crate) last_sync_time: Option<u64>,
+    pub(crate) last_sync_user: Option<String>,
+}
+
+/// Record the last sync of a dataset, in the transaction saving its entries
+pub(super) async fn insert_sync(
+    transaction: &mut Transaction<'_, Sqlite>,
+    team: &str,
+    service: &str,
+    username: &str,
+    sync_time: u64,
+) -> Result<(), String> {
+    let query = "INSERT OR REPLACE INTO dataset_sync (team, service, last_sync_time, last_sync_user) VALUES (?, ?, ?, ?)";
+    sqlx::query(query)
+        .bind(team)
+        .bind(service)
+        .bind(sync_time as i64)
+        .bind(username)
+        .execute(&mut **transaction)
+        .await
+        .map(|_| ())
+        .map_err(|err| format!("Error recording sync of {}/{}: {}", team, service, err))
+}
+
+impl DataRepository {
+    /// The datasets of a team with their entry count and last sync
+    pub(crate) async fn list_team_datasets(&self, team: &str) -> Result<Vec<DatasetInfo>, String> {
+        info!("list_team_datasets team={}", team);
+        let mut datasets: BTreeMap<String, DatasetInfo> = self
+            .data_store
+            .list_team(team)
+            .await?
+            .into_iter()
+            .map(|stored_dataset| {
+                (
+                    stored_dataset.service.clone(),
+                    DatasetInfo {
+                        service: stored_dataset.service,
+                        entries: stored_dataset.entries,
+                        last_sync_time: None,
+                        last_sync_user: None,
+                    },
+                )
+            })
+            .collect();
+        let query =
+            "SELECT service, last_sync_time, last_sync_user FROM dataset_sync WHERE team = ?";
+        let rows = sqlx::query(query)
+            .bind(team)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|err| format!("Error listing datasets of {}: {}", team, err))?;
+        for row in rows {
+            let service: String = row.get(0);
+            let dataset = datasets
+                .entry(service.clone())
+                .or_insert_with(|| DatasetInfo {
+                    service,
+                    entries: 0,
+                    last_sync_time: None,
+                    last_sync_user: None,
+                });
+            dataset.last_sync_time = Some(row.get::<i64, _>(1) as u64);
+            dataset.last_sync_user = Some(row.get(2));
+        }
+        Ok(datasets.into_values().collect())
+    }
+}
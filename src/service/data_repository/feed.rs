@@ -0,0 +1,91 @@
+use log::info;
+use serde::{Deserialize, Serialize};
+use sqlx::Row;
+use sqlx::sqlite::SqliteRow;
+
+use crate::service::data_repository::DataRepository;
+
+/// An opendata feed pulled by the server, its roadworks are added to the datasets of the subscribed teams
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct OpendataFeed {
+    /// The opendata service the feed belongs to
+    pub(crate) service: String,
+    /// An http(s) url or the path of a file of the feeds directory
+    pub(crate) url: String,
+    /// The dotted path of the roadwork array in the feed document, empty if the document is the array
+    #[serde(default)]
+    pub(crate) items_path: String,
+    /// The dotted path of the roadwork id in each item
+    pub(crate) id_field: String,
+    /// The teams receiving the roadworks of the feed
+    #[serde(default)]
+    pub(crate) teams: Vec<String>,
+}
+
+impl DataRepository {
+    pub(crate) async fn list_feeds(&self) -> Result<Vec<OpendataFeed>, String> {
+        info!("list_feeds");
+        let query =
+            "SELECT service, url, items_path, id_field, teams FROM opendata_feed ORDER BY service";
+        let rows = sqlx::query(query)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|err| format!("Error listing opendata feeds: {}", err))?;
+        Ok(rows.iter().map(read_feed).collect())
+    }
+
+    pub(crate) async fn find_feed(&self, service: &str) -> Result<Option<OpendataFeed>, String> {
+        let query =
+            "SELECT service, url, items_path, id_field, teams FROM opendata_feed WHERE service = ?";
+        let row = sqlx::query(query)
+            .bind(service)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|err| format!("Error finding opendata feed {}: {}", service, err))?;
+        Ok(row.as_ref().map(read_feed))
+    }
+
+    /// Insert or replace the feed of an opendata service
+    pub(crate) async fn save_feed(&self, feed: &OpendataFeed) -> Result<(), String> {
+        info!("save_feed service={} url={}", feed.service, feed.url);
+        let query = "INSERT OR REPLACE INTO opendata_feed (service, url, items_path, id_field, teams) VALUES (?, ?, ?, ?, ?)";
+        sqlx::query(query)
+            .bind(&feed.service)
+            .bind(&feed.url)
+            .bind(&feed.items_path)
+            .bind(&feed.id_field)
+            .bind(serde_json::to_string(&feed.teams).unwrap_or_default())
+            .execute(&self.pool)
+            .await
+            .map(|_| ())
+            .map_err(|err| format!("Error saving opendata feed {}: {}", feed.service, err))
+    }
+
+    pub(crate) async fn delete_feed(&self, service: &str) -> Result<(), String> {
+        info!("delete_feed service={}", service);
+        sqlx::query("DELETE FROM opendata_feed WHERE service = ?")
+            .bind(service)
+            .execute(&self.pool)
+            .await
+            .map(|_| ())
+            .map_err(|err| format!("Error deleting opendata feed {}: {}", service, err))
+    }
+}
+
+/// The teams are a json array, the feeds saved before were comma separated
+fn read_feed(row: &SqliteRow) -> OpendataFeed {
+    let teams: String = row.get(4);
+    OpendataFeed {
+        service: row.get(0),
+        url: row.get(1),
+        items_path: row.get(2),
+        id_field: row.get(3),
+        teams: serde_json::from_str(&teams).unwrap_or_else(|_| {
+            teams
+                .split(',')
+                .filter(|team| !team.is_empty())
+                .map(str::to_string)
+                .collect()
+        }),
+    }
+}
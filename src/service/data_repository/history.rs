@@ -0,0 +1,78 @@
+use log::info;
+use serde::Serialize;
+use sqlx::{Row, Sqlite, Transaction};
+
+use crate::service::data_repository::DataRepository;
+
+/// A status change of a roadwork entry, the old status is missing when the entry was created
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct StatusChange {
+    pub(crate) id: String,
+    pub(crate) old_status: Option<String>,
+    pub(crate) new_status: String,
+    pub(crate) time: u64,
+    pub(crate) team: String,
+    pub(crate) service: String,
+    pub(crate) username: String,
+}
+
+impl DataRepository {
+    pub(crate) async fn load_history(
+        &self,
+        team: &str,
+        service: &str,
+        id: &str,
+    ) -> Result<Vec<StatusChange>, String> {
+        info!("load_history team={} service={} id={}", team, service, id);
+        let query = "
+        SELECT old_status, new_status, time, username FROM sync_data_history
+        WHERE team = ? AND service = ? AND roadwork_id = ?
+        ORDER BY time, rowid";
+        let rows = sqlx::query(query)
+            .bind(team)
+            .bind(service)
+            .bind(id)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|err| {
+                format!(
+                    "Error loading history of {}/{}/{}: {}",
+                    team, service, id, err
+                )
+            })?;
+        Ok(rows
+            .iter()
+            .map(|row| StatusChange {
+                id: id.to_string(),
+                old_status: row.get(0),
+                new_status: row.get(1),
+                time: row.get::<i64, _>(2) as u64,
+                team: team.to_string(),
+                service: service.to_string(),
+                username: row.get(3),
+            })
+            .collect())
+    }
+}
+
+pub(super) async fn insert_history(
+    transaction: &mut Transaction<'_, Sqlite>,
+    status_changes: &[StatusChange],
+) -> Result<(), sqlx::Error> {
+    let query = "
+    INSERT INTO sync_data_history (team, service, roadwork_id, old_status, new_status, time, username)
+    VALUES (?, ?, ?, ?, ?, ?, ?)";
+    for status_change in status_changes {
+        sqlx::query(query)
+            .bind(&status_change.team)
+            .bind(&status_change.service)
+            .bind(&status_change.id)
+            .bind(&status_change.old_status)
+            .bind(&status_change.new_status)
+            .bind(status_change.time as i64)
+            .bind(&status_change.username)
+            .execute(&mut **transaction)
+            .await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,91 @@
+use std::collections::HashMap;
+use std::fs;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use log::{info, warn};
+use roadwork_sync_lib::sync_data::SyncData;
+
+use crate::service::data::now;
+use crate::service::data_repository::{DataRepository, DatasetUpdate};
+
+/// Directory where the datasets were stored as json files before they moved to the database
+pub(super) const LEGACY_DATA_DIR: &str = "data";
+
+impl DataRepository {
+    /// Import the legacy data/{team}/{service}.json files.
+    /// Each imported file is renamed to {service}.json.imported so the import happens only once,
+    /// a corrupted file is renamed to {service}.json.corrupted-{time} and its dataset quarantined
+    pub(super) async fn import_json_files(&self) {
+        let Ok(team_dirs) = fs::read_dir(LEGACY_DATA_DIR) else {
+            return;
+        };
+        for team_dir in team_dirs.flatten() {
+            let team_path = team_dir.path();
+            if !team_path.is_dir() {
+                continue;
+            }
+            let team = team_dir.file_name().to_string_lossy().to_string();
+            let Ok(files) = fs::read_dir(&team_path) else {
+                warn!("Unable to read directory {}", team_path.display());
+                continue;
+            };
+            for file in files.flatten() {
+                let path = file.path();
+                if path.extension().is_some_and(|extension| extension == "json") {
+                    self.import_json_file(&team, &path).await;
+                }
+            }
+        }
+    }
+
+    async fn import_json_file(&self, team: &str, path: &Path) {
+        let Some(service) = path.file_stem().map(|stem| stem.to_string_lossy().to_string()) else {
+            return;
+        };
+        info!("import {}", path.display());
+        let sync_data_list: HashMap<String, SyncData> = match File::open(path)
+            .map_err(|err| err.to_string())
+            .and_then(|file| {
+                serde_json::from_reader(BufReader::new(file)).map_err(|err| err.to_string())
+            }) {
+            Ok(sync_data_list) => sync_data_list,
+            Err(err) => {
+                warn!("Unable to import {}: {}", path.display(), err);
+                self.quarantine_json_file(team, &service, path, &err).await;
+                return;
+            }
+        };
+        let update = DatasetUpdate {
+            sync_data_list,
+            ..DatasetUpdate::default()
+        };
+        if let Err(err) = self.save(team, &service, &update).await {
+            warn!("Unable to import {}: {}", path.display(), err);
+            return;
+        }
+        let imported_path = path.with_extension("json.imported");
+        if let Err(err) = fs::rename(path, &imported_path) {
+            warn!("Unable to rename {}: {}", path.display(), err);
+        }
+        info!("imported {} entries from {}", update.sync_data_list.len(), path.display());
+    }
+
+    /// Keep the corrupted file aside under a timestamped name and quarantine its dataset
+    /// so the clients cannot fill it before an administrator looks at it
+    async fn quarantine_json_file(&self, team: &str, service: &str, path: &Path, error: &str) {
+        let quarantine_time = now();
+        let quarantined_path = path.with_extension(format!("json.corrupted-{}", quarantine_time));
+        if let Err(err) = fs::rename(path, &quarantined_path) {
+            warn!("Unable to rename {}: {}", path.display(), err);
+        }
+        let reason = format!("Corrupted file {}: {}", quarantined_path.display(), error);
+        if let Err(err) = self
+            .quarantine_dataset(team, service, &reason, quarantine_time)
+            .await
+        {
+            warn!("Unable to quarantine {}: {}", path.display(), err);
+        }
+    }
+}
@@ -0,0 +1,58 @@
+use std::collections::HashMap;
+
+use log::info;
+use sqlx::Row;
+
+use crate::service::data_repository::DataRepository;
+
+impl DataRepository {
+    /// The merge strategy name by opendata service
+    pub(crate) async fn list_merge_strategies(&self) -> Result<HashMap<String, String>, String> {
+        info!("list_merge_strategies");
+        let rows = sqlx::query("SELECT service, strategy FROM merge_strategy")
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|err| format!("Error listing merge strategies: {}", err))?;
+        Ok(rows.iter().map(|row| (row.get(0), row.get(1))).collect())
+    }
+
+    pub(crate) async fn find_merge_strategy(
+        &self,
+        service: &str,
+    ) -> Result<Option<String>, String> {
+        let row = sqlx::query("SELECT strategy FROM merge_strategy WHERE service = ?")
+            .bind(service)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|err| format!("Error finding merge strategy of {}: {}", service, err))?;
+        Ok(row.map(|row| row.get(0)))
+    }
+
+    pub(crate) async fn set_merge_strategy(
+        &self,
+        service: &str,
+        strategy: &str,
+    ) -> Result<(), String> {
+        info!(
+            "set_merge_strategy service={} strategy={}",
+            service, strategy
+        );
+        sqlx::query("INSERT OR REPLACE INTO merge_strategy (service, strategy) VALUES (?, ?)")
+            .bind(service)
+            .bind(strategy)
+            .execute(&self.pool)
+            .await
+            .map(|_| ())
+            .map_err(|err| format!("Error setting merge strategy of {}: {}", service, err))
+    }
+
+    pub(crate) async fn delete_merge_strategy(&self, service: &str) -> Result<(), String> {
+        info!("delete_merge_strategy service={}", service);
+        sqlx::query("DELETE FROM merge_strategy WHERE service = ?")
+            .bind(service)
+            .execute(&self.pool)
+            .await
+            .map(|_| ())
+            .map_err(|err| format!("Error deleting merge strategy of {}: {}", service, err))
+    }
+}
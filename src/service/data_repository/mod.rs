@@ -0,0 +1,281 @@
+mod attribution;
+mod dataset;
+mod feed;
+mod history;
+mod import;
+mod merge_strategy;
+mod opendata_service;
+mod quarantine;
+mod retention;
+mod snapshot;
+mod tombstone;
+mod webhook;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use log::info;
+use roadwork_sync_lib::sync_data::SyncData;
+use sqlx::{Executor, Pool, Sqlite};
+
+use crate::config::Config;
+use crate::error::Error;
+use crate::service::data::now;
+use crate::service::data_repository::import::LEGACY_DATA_DIR;
+use crate::service::data_store;
+use crate::service::data_store::{DataStore, DataStoreKind};
+
+pub(crate) use attribution::Attribution;
+pub(crate) use dataset::DatasetInfo;
+pub(crate) use feed::OpendataFeed;
+pub(crate) use history::StatusChange;
+pub(crate) use opendata_service::OpendataService;
+pub(crate) use quarantine::{QuarantinedDataset, QuarantinedEntry};
+pub(crate) use retention::{RetentionPolicy, StaleEntry};
+pub(crate) use snapshot::{NewSnapshot, SnapshotInfo};
+pub(crate) use webhook::{Webhook, WebhookDelivery};
+
+/// A change of a dataset, written in one transaction
+#[derive(Debug, Default)]
+pub(crate) struct DatasetUpdate {
+    /// The inserted or updated entries, the other entries of the dataset are left untouched.
+    /// Their tombstones are removed as they exist again
+    pub(crate) sync_data_list: HashMap<String, SyncData>,
+    /// The deleted entries, a tombstone is kept for each
+    pub(crate) deleted_ids: Vec<String>,
+    pub(crate) deletion_time: u64,
+    pub(crate) status_changes: Vec<StatusChange>,
+    pub(crate) snapshot: Option<NewSnapshot>,
+    /// The user who modified the entries and the time, recorded as their attribution
+    pub(crate) attribution: Option<(String, u64)>,
+    /// The user and the time of the sync, recorded as the last sync of the dataset
+    pub(crate) last_sync: Option<(String, u64)>,
+    /// Lift the quarantine of the dataset, once it is restored
+    pub(crate) release_quarantine: bool,
+}
+
+#[derive(Clone)]
+pub(crate) struct DataRepository {
+    pool: Pool<Sqlite>,
+    /// The entries of the datasets, the other tables are always in the database
+    data_store: Arc<dyn DataStore>,
+}
+
+impl DataRepository {
+    pub(crate) async fn new(pool: Pool<Sqlite>, config: &Config) -> Result<Self, Error> {
+        let data_store = data_store::open(config, pool.clone()).await?;
+        let repository = DataRepository { pool, data_store };
+        repository.init_db().await?;
+        // the filesystem store reads the legacy files where they are,
+        // the memory store would lose them at the next restart once they are renamed
+        let import_json_files = match config.data_store {
+            DataStoreKind::Sqlite => true,
+            DataStoreKind::Filesystem => config.data_dir != LEGACY_DATA_DIR,
+            DataStoreKind::Memory => false,
+        };
+        if import_json_files {
+            repository.import_json_files().await;
+        }
+        repository.init_opendata_services().await?;
+        Ok(repository)
+    }
+
+    async fn init_db(&self) -> Result<(), sqlx::Error> {
+        let query = "
+        CREATE TABLE IF NOT EXISTS sync_data_history (
+            team TEXT NOT NULL,
+            service TEXT NOT NULL,
+            roadwork_id TEXT NOT NULL,
+            old_status TEXT,
+            new_status TEXT NOT NULL,
+            time INTEGER NOT NULL,
+            username TEXT NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS sync_data_history_roadwork_id
+            ON sync_data_history (team, service, roadwork_id);
+        CREATE TABLE IF NOT EXISTS sync_data_tombstone (
+            team TEXT NOT NULL,
+            service TEXT NOT NULL,
+            id TEXT NOT NULL,
+            deletion_time INTEGER NOT NULL,
+            PRIMARY KEY (team, service, id)
+        );
+        CREATE TABLE IF NOT EXISTS retention_policy (
+            team TEXT NOT NULL,
+            service TEXT NOT NULL,
+            retention_days INTEGER NOT NULL,
+            PRIMARY KEY (team, service)
+        );
+        CREATE TABLE IF NOT EXISTS sync_data_archive (
+            team TEXT NOT NULL,
+            service TEXT NOT NULL,
+            id TEXT NOT NULL,
+            status TEXT NOT NULL,
+            server_update_time INTEGER NOT NULL,
+            data TEXT NOT NULL,
+            archive_time INTEGER NOT NULL,
+            PRIMARY KEY (team, service, id)
+        );
+        CREATE TABLE IF NOT EXISTS dataset_sync (
+            team TEXT NOT NULL,
+            service TEXT NOT NULL,
+            last_sync_time INTEGER NOT NULL,
+            last_sync_user TEXT NOT NULL,
+            PRIMARY KEY (team, service)
+        );
+        CREATE TABLE IF NOT EXISTS dataset_snapshot (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            team TEXT NOT NULL,
+            service TEXT NOT NULL,
+            time INTEGER NOT NULL,
+            username TEXT NOT NULL,
+            entries INTEGER NOT NULL,
+            data TEXT NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS dataset_snapshot_team_service
+            ON dataset_snapshot (team, service);
+        CREATE TABLE IF NOT EXISTS merge_strategy (
+            service TEXT PRIMARY KEY,
+            strategy TEXT NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS sync_data_attribution (
+            team TEXT NOT NULL,
+            service TEXT NOT NULL,
+            id TEXT NOT NULL,
+            username TEXT NOT NULL,
+            time INTEGER NOT NULL,
+            PRIMARY KEY (team, service, id)
+        );
+        CREATE TABLE IF NOT EXISTS webhook (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            team TEXT NOT NULL,
+            url TEXT NOT NULL,
+            secret TEXT NOT NULL,
+            services TEXT NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS webhook_delivery (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            webhook_id INTEGER NOT NULL,
+            event TEXT NOT NULL,
+            attempt INTEGER NOT NULL,
+            time INTEGER NOT NULL,
+            status_code INTEGER,
+            error TEXT,
+            pending INTEGER NOT NULL DEFAULT 0
+        );
+        CREATE INDEX IF NOT EXISTS webhook_delivery_webhook_id
+            ON webhook_delivery (webhook_id);
+        CREATE TABLE IF NOT EXISTS opendata_feed (
+            service TEXT PRIMARY KEY,
+            url TEXT NOT NULL,
+            items_path TEXT NOT NULL,
+            id_field TEXT NOT NULL,
+            teams TEXT NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS dataset_quarantine (
+            team TEXT NOT NULL,
+            service TEXT NOT NULL,
+            quarantine_time INTEGER NOT NULL,
+            reason TEXT NOT NULL,
+            PRIMARY KEY (team, service)
+        );
+        CREATE TABLE IF NOT EXISTS sync_data_quarantine (
+            team TEXT NOT NULL,
+            service TEXT NOT NULL,
+            id TEXT NOT NULL,
+            data TEXT NOT NULL,
+            error TEXT NOT NULL,
+            quarantine_time INTEGER NOT NULL,
+            PRIMARY KEY (team, service, id)
+        );
+    ";
+        self.pool.execute(query).await?;
+        info!("Data tables initialized");
+        Ok(())
+    }
+
+    /// Load the entries of a dataset. The entries that cannot be read are moved to the quarantine
+    /// instead of being dropped, so the next save cannot overwrite them silently
+    pub(crate) async fn load(
+        &self,
+        team: &str,
+        service: &str,
+    ) -> Result<HashMap<String, SyncData>, String> {
+        let stored_dataset = self.data_store.load(team, service).await?;
+        if !stored_dataset.corrupt_entries.is_empty() {
+            self.quarantine_entries(team, service, &stored_dataset.corrupt_entries, now())
+                .await?;
+        }
+        Ok(stored_dataset.entries)
+    }
+
+    /// Load the entries of a dataset modified at or after the given server time
+    pub(crate) async fn load_since(
+        &self,
+        team: &str,
+        service: &str,
+        since: u64,
+    ) -> Result<HashMap<String, SyncData>, String> {
+        let stored_dataset = self.data_store.load_since(team, service, since).await?;
+        if !stored_dataset.corrupt_entries.is_empty() {
+            self.quarantine_entries(team, service, &stored_dataset.corrupt_entries, now())
+                .await?;
+        }
+        Ok(stored_dataset.entries)
+    }
+
+    /// Write a change of a dataset: the saved and deleted entries with their tombstones, status changes,
+    /// snapshot, attribution, last sync and quarantine release are written in one transaction,
+    /// see DataStore for the stores outside the database
+    pub(crate) async fn save(
+        &self,
+        team: &str,
+        service: &str,
+        update: &DatasetUpdate,
+    ) -> Result<(), String> {
+        let to_error =
+            |err: sqlx::Error| format!("Error saving data for {}/{}: {}", team, service, err);
+        let mut transaction = self.pool.begin().await.map_err(to_error)?;
+        if !update.deleted_ids.is_empty() {
+            self.data_store
+                .delete(&mut transaction, team, service, &update.deleted_ids)
+                .await?;
+            tombstone::insert_tombstones(
+                &mut transaction,
+                team,
+                service,
+                &update.deleted_ids,
+                update.deletion_time,
+            )
+            .await?;
+        }
+        self.data_store
+            .save(&mut transaction, team, service, &update.sync_data_list)
+            .await?;
+        tombstone::delete_tombstones(
+            &mut transaction,
+            team,
+            service,
+            update.sync_data_list.keys(),
+        )
+        .await?;
+        history::insert_history(&mut transaction, &update.status_changes)
+            .await
+            .map_err(to_error)?;
+        if let Some(snapshot) = &update.snapshot {
+            snapshot::insert_snapshot(&mut transaction, team, service, snapshot).await?;
+        }
+        if let Some((username, time)) = &update.attribution {
+            let ids: Vec<&String> = update.sync_data_list.keys().collect();
+            attribution::insert_attribution(&mut transaction, team, service, &ids, username, *time)
+                .await?;
+        }
+        if let Some((username, sync_time)) = &update.last_sync {
+            dataset::insert_sync(&mut transaction, team, service, username, *sync_time).await?;
+        }
+        if update.release_quarantine {
+            quarantine::delete_quarantine(&mut transaction, team, service).await?;
+        }
+        transaction.commit().await.map_err(to_error)
+    }
+}
@@ -0,0 +1,123 @@
+use log::info;
+use serde::{Deserialize, Serialize};
+use sqlx::{Executor, Row};
+
+use crate::error::Error;
+use crate::service::data_repository::DataRepository;
+
+/// An opendata service of the catalog, the teams can only sync the services of the catalog
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct OpendataService {
+    pub(crate) id: String,
+    pub(crate) label: String,
+    #[serde(default)]
+    pub(crate) description: String,
+    /// What the statuses mean for this service
+    #[serde(default)]
+    pub(crate) status_meaning: String,
+}
+
+impl DataRepository {
+    /// Create the catalog, the first time it is filled with the services already having data
+    pub(super) async fn init_opendata_services(&self) -> Result<(), Error> {
+        let exists = sqlx::query(
+            "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = 'opendata_service'",
+        )
+        .fetch_one(&self.pool)
+        .await?
+        .get::<i64, _>(0)
+            > 0;
+        if exists {
+            return Ok(());
+        }
+        let query = "
+        CREATE TABLE opendata_service (
+            id TEXT PRIMARY KEY,
+            label TEXT NOT NULL,
+            description TEXT NOT NULL DEFAULT '',
+            status_meaning TEXT NOT NULL DEFAULT ''
+        );
+    ";
+        self.pool.execute(query).await?;
+        for stored_dataset in self.data_store.list().await.map_err(Error::InitError)? {
+            sqlx::query("INSERT OR IGNORE INTO opendata_service (id, label) VALUES (?, ?)")
+                .bind(&stored_dataset.service)
+                .bind(&stored_dataset.service)
+                .execute(&self.pool)
+                .await?;
+        }
+        info!("Opendata service catalog initialized");
+        Ok(())
+    }
+
+    pub(crate) async fn list_opendata_services(&self) -> Result<Vec<OpendataService>, String> {
+        info!("list_opendata_services");
+        let query =
+            "SELECT id, label, description, status_meaning FROM opendata_service ORDER BY id";
+        let rows = sqlx::query(query)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|err| format!("Error listing opendata services: {}", err))?;
+        Ok(rows
+            .iter()
+            .map(|row| OpendataService {
+                id: row.get(0),
+                label: row.get(1),
+                description: row.get(2),
+                status_meaning: row.get(3),
+            })
+            .collect())
+    }
+
+    pub(crate) async fn find_opendata_service(
+        &self,
+        id: &str,
+    ) -> Result<Option<OpendataService>, String> {
+        let query =
+            "SELECT id, label, description, status_meaning FROM opendata_service WHERE id = ?";
+        let row = sqlx::query(query)
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|err| format!("Error finding opendata service {}: {}", id, err))?;
+        Ok(row.map(|row| OpendataService {
+            id: row.get(0),
+            label: row.get(1),
+            description: row.get(2),
+            status_meaning: row.get(3),
+        }))
+    }
+
+    /// Insert or replace an opendata service of the catalog
+    pub(crate) async fn save_opendata_service(
+        &self,
+        opendata_service: &OpendataService,
+    ) -> Result<(), String> {
+        info!("save_opendata_service {:?}", opendata_service);
+        let query = "INSERT OR REPLACE INTO opendata_service (id, label, description, status_meaning) VALUES (?, ?, ?, ?)";
+        sqlx::query(query)
+            .bind(&opendata_service.id)
+            .bind(&opendata_service.label)
+            .bind(&opendata_service.description)
+            .bind(&opendata_service.status_meaning)
+            .execute(&self.pool)
+            .await
+            .map(|_| ())
+            .map_err(|err| {
+                format!(
+                    "Error saving opendata service {}: {}",
+                    opendata_service.id, err
+                )
+            })
+    }
+
+    pub(crate) async fn delete_opendata_service(&self, id: &str) -> Result<(), String> {
+        info!("delete_opendata_service {}", id);
+        sqlx::query("DELETE FROM opendata_service WHERE id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map(|_| ())
+            .map_err(|err| format!("Error deleting opendata service {}: {}", id, err))
+    }
+}
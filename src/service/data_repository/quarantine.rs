@@ -0,0 +1,235 @@
+use log::{info, warn};
+use serde::Serialize;
+use sqlx::{Row, Sqlite, Transaction};
+
+use crate::service::data_repository::DataRepository;
+use crate::service::data_store::CorruptEntry;
+
+/// A dataset refusing the syncs until an administrator repairs or restores it
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct QuarantinedDataset {
+    pub(crate) team: String,
+    pub(crate) service: String,
+    pub(crate) quarantine_time: u64,
+    pub(crate) reason: String,
+    /// The ids of the entries that could not be read, empty if a whole file was corrupted
+    pub(crate) entries: Vec<String>,
+}
+
+/// The raw data of an entry that could not be read, kept until an administrator purges it
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct QuarantinedEntry {
+    pub(crate) id: String,
+    pub(crate) data: String,
+    pub(crate) error: String,
+    pub(crate) quarantine_time: u64,
+}
+
+/// Lift the quarantine of a dataset, in the transaction repairing or restoring it
+pub(super) async fn delete_quarantine(
+    transaction: &mut Transaction<'_, Sqlite>,
+    team: &str,
+    service: &str,
+) -> Result<(), String> {
+    info!("release_quarantine team={} service={}", team, service);
+    sqlx::query("DELETE FROM dataset_quarantine WHERE team = ? AND service = ?")
+        .bind(team)
+        .bind(service)
+        .execute(&mut **transaction)
+        .await
+        .map(|_| ())
+        .map_err(|err| {
+            format!(
+                "Error releasing quarantine of {}/{}: {}",
+                team, service, err
+            )
+        })
+}
+
+impl DataRepository {
+    /// Quarantine the dataset and move the corrupt entries out of it.
+    /// The raw data of the entries is committed before they are removed from the store,
+    /// so it is never lost whatever the store
+    pub(super) async fn quarantine_entries(
+        &self,
+        team: &str,
+        service: &str,
+        corrupt_entries: &[CorruptEntry],
+        quarantine_time: u64,
+    ) -> Result<(), String> {
+        warn!(
+            "quarantine team={} service={} entries={}",
+            team,
+            service,
+            corrupt_entries.len()
+        );
+        let to_error =
+            |err: sqlx::Error| format!("Error quarantining {}/{}: {}", team, service, err);
+        let mut transaction = self.pool.begin().await.map_err(to_error)?;
+        for corrupt_entry in corrupt_entries {
+            sqlx::query("INSERT OR REPLACE INTO sync_data_quarantine (team, service, id, data, error, quarantine_time) VALUES (?, ?, ?, ?, ?, ?)")
+                .bind(team)
+                .bind(service)
+                .bind(&corrupt_entry.id)
+                .bind(&corrupt_entry.data)
+                .bind(&corrupt_entry.error)
+                .bind(quarantine_time as i64)
+                .execute(&mut *transaction)
+                .await
+                .map_err(to_error)?;
+        }
+        let reason = format!("{} corrupted entries", corrupt_entries.len());
+        sqlx::query("INSERT OR IGNORE INTO dataset_quarantine (team, service, quarantine_time, reason) VALUES (?, ?, ?, ?)")
+            .bind(team)
+            .bind(service)
+            .bind(quarantine_time as i64)
+            .bind(reason)
+            .execute(&mut *transaction)
+            .await
+            .map_err(to_error)?;
+        transaction.commit().await.map_err(to_error)?;
+        let ids: Vec<String> = corrupt_entries
+            .iter()
+            .map(|corrupt_entry| corrupt_entry.id.clone())
+            .collect();
+        let mut transaction = self.pool.begin().await.map_err(to_error)?;
+        self.data_store
+            .delete(&mut transaction, team, service, &ids)
+            .await?;
+        transaction.commit().await.map_err(to_error)
+    }
+
+    /// Quarantine a whole dataset, for instance when its legacy file is corrupted
+    pub(crate) async fn quarantine_dataset(
+        &self,
+        team: &str,
+        service: &str,
+        reason: &str,
+        quarantine_time: u64,
+    ) -> Result<(), String> {
+        warn!(
+            "quarantine team={} service={} reason={}",
+            team, service, reason
+        );
+        let query = "INSERT OR IGNORE INTO dataset_quarantine (team, service, quarantine_time, reason) VALUES (?, ?, ?, ?)";
+        sqlx::query(query)
+            .bind(team)
+            .bind(service)
+            .bind(quarantine_time as i64)
+            .bind(reason)
+            .execute(&self.pool)
+            .await
+            .map(|_| ())
+            .map_err(|err| format!("Error quarantining {}/{}: {}", team, service, err))
+    }
+
+    pub(crate) async fn is_quarantined(&self, team: &str, service: &str) -> Result<bool, String> {
+        let query = "SELECT COUNT(*) FROM dataset_quarantine WHERE team = ? AND service = ?";
+        let count: i64 = sqlx::query_scalar(query)
+            .bind(team)
+            .bind(service)
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|err| format!("Error checking quarantine of {}/{}: {}", team, service, err))?;
+        Ok(count > 0)
+    }
+
+    pub(crate) async fn list_quarantined(&self) -> Result<Vec<QuarantinedDataset>, String> {
+        info!("list_quarantined");
+        let to_error = |err: sqlx::Error| format!("Error listing quarantined datasets: {}", err);
+        let rows = sqlx::query(
+            "SELECT team, service, quarantine_time, reason FROM dataset_quarantine ORDER BY team, service",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(to_error)?;
+        let mut quarantined_datasets = Vec::with_capacity(rows.len());
+        for row in rows {
+            let team: String = row.get(0);
+            let service: String = row.get(1);
+            let entries = sqlx::query_scalar(
+                "SELECT id FROM sync_data_quarantine WHERE team = ? AND service = ? ORDER BY id",
+            )
+            .bind(&team)
+            .bind(&service)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(to_error)?;
+            quarantined_datasets.push(QuarantinedDataset {
+                team,
+                service,
+                quarantine_time: row.get::<i64, _>(2) as u64,
+                reason: row.get(3),
+                entries,
+            });
+        }
+        Ok(quarantined_datasets)
+    }
+
+    /// Lift the quarantine of a dataset, the raw data of its corrupt entries is kept until purged
+    pub(crate) async fn release_quarantine(&self, team: &str, service: &str) -> Result<(), String> {
+        let to_error = |err: sqlx::Error| {
+            format!(
+                "Error releasing quarantine of {}/{}: {}",
+                team, service, err
+            )
+        };
+        let mut transaction = self.pool.begin().await.map_err(to_error)?;
+        delete_quarantine(&mut transaction, team, service).await?;
+        transaction.commit().await.map_err(to_error)
+    }
+
+    /// The raw data of the corrupt entries of a dataset
+    pub(crate) async fn list_quarantined_entries(
+        &self,
+        team: &str,
+        service: &str,
+    ) -> Result<Vec<QuarantinedEntry>, String> {
+        info!("list_quarantined_entries team={} service={}", team, service);
+        let query = "SELECT id, data, error, quarantine_time FROM sync_data_quarantine WHERE team = ? AND service = ? ORDER BY id";
+        let rows = sqlx::query(query)
+            .bind(team)
+            .bind(service)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|err| {
+                format!(
+                    "Error listing quarantined entries of {}/{}: {}",
+                    team, service, err
+                )
+            })?;
+        Ok(rows
+            .iter()
+            .map(|row| QuarantinedEntry {
+                id: row.get(0),
+                data: row.get(1),
+                error: row.get(2),
+                quarantine_time: row.get::<i64, _>(3) as u64,
+            })
+            .collect())
+    }
+
+    /// Forget the raw data of the corrupt entries of a dataset, returns how many were removed
+    pub(crate) async fn purge_quarantined_entries(
+        &self,
+        team: &str,
+        service: &str,
+    ) -> Result<u64, String> {
+        info!(
+            "purge_quarantined_entries team={} service={}",
+            team, service
+        );
+        sqlx::query("DELETE FROM sync_data_quarantine WHERE team = ? AND service = ?")
+            .bind(team)
+            .bind(service)
+            .execute(&self.pool)
+            .await
+            .map(|result| result.rows_affected())
+            .map_err(|err| {
+                format!(
+                    "Error purging quarantined entries of {}/{}: {}",
+                    team, service, err
+                )
+            })
+    }
+}
@@ -0,0 +1,164 @@
+use std::collections::HashMap;
+
+use log::info;
+use roadwork_sync_lib::sync_data::SyncData;
+use serde::Serialize;
+use sqlx::{Row, Sqlite, Transaction};
+
+use crate::service::data_repository::DataRepository;
+
+/// How long the entries of a team dataset are kept after their last server update.
+/// The service "*" applies to every service of the team without a specific policy
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct RetentionPolicy {
+    pub(crate) team: String,
+    pub(crate) service: String,
+    pub(crate) retention_days: u64,
+}
+
+/// An entry not updated since the retention of its dataset
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct StaleEntry {
+    pub(crate) team: String,
+    pub(crate) service: String,
+    pub(crate) id: String,
+    pub(crate) status: String,
+    pub(crate) server_update_time: u64,
+}
+
+impl DataRepository {
+    pub(crate) async fn list_retention_policies(&self) -> Result<Vec<RetentionPolicy>, String> {
+        info!("list_retention_policies");
+        let query =
+            "SELECT team, service, retention_days FROM retention_policy ORDER BY team, service";
+        let rows = sqlx::query(query)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|err| format!("Error listing retention policies: {}", err))?;
+        Ok(rows
+            .iter()
+            .map(|row| RetentionPolicy {
+                team: row.get(0),
+                service: row.get(1),
+                retention_days: row.get::<i64, _>(2) as u64,
+            })
+            .collect())
+    }
+
+    pub(crate) async fn set_retention_policy(
+        &self,
+        team: &str,
+        service: &str,
+        retention_days: u64,
+    ) -> Result<(), String> {
+        info!(
+            "set_retention_policy team={} service={} days={}",
+            team, service, retention_days
+        );
+        let query = "INSERT OR REPLACE INTO retention_policy (team, service, retention_days) VALUES (?, ?, ?)";
+        sqlx::query(query)
+            .bind(team)
+            .bind(service)
+            .bind(retention_days as i64)
+            .execute(&self.pool)
+            .await
+            .map(|_| ())
+            .map_err(|err| {
+                format!(
+                    "Error setting retention policy for {}/{}: {}",
+                    team, service, err
+                )
+            })
+    }
+
+    pub(crate) async fn delete_retention_policy(
+        &self,
+        team: &str,
+        service: &str,
+    ) -> Result<(), String> {
+        info!("delete_retention_policy team={} service={}", team, service);
+        let query = "DELETE FROM retention_policy WHERE team = ? AND service = ?";
+        sqlx::query(query)
+            .bind(team)
+            .bind(service)
+            .execute(&self.pool)
+            .await
+            .map(|_| ())
+            .map_err(|err| {
+                format!(
+                    "Error deleting retention policy for {}/{}: {}",
+                    team, service, err
+                )
+            })
+    }
+
+    /// The (team, service) pairs having data
+    pub(crate) async fn list_datasets(&self) -> Result<Vec<(String, String)>, String> {
+        Ok(self
+            .data_store
+            .list()
+            .await?
+            .into_iter()
+            .map(|stored_dataset| (stored_dataset.team, stored_dataset.service))
+            .collect())
+    }
+
+    /// The entries of a dataset whose last server update is before the given time
+    pub(crate) async fn find_stale_entries(
+        &self,
+        team: &str,
+        service: &str,
+        before: u64,
+    ) -> Result<Vec<StaleEntry>, String> {
+        let mut stale_entries: Vec<StaleEntry> = self
+            .load(team, service)
+            .await?
+            .into_iter()
+            .filter(|(_, sync_data)| sync_data.server_update_time < before)
+            .map(|(id, sync_data)| StaleEntry {
+                team: team.to_string(),
+                service: service.to_string(),
+                id,
+                status: sync_data.status.to_string(),
+                server_update_time: sync_data.server_update_time,
+            })
+            .collect();
+        stale_entries.sort_by_key(|stale_entry| stale_entry.server_update_time);
+        Ok(stale_entries)
+    }
+}
+
+/// Copy entries to the archive table, they are kept there when they are deleted from the dataset
+pub(super) async fn insert_archive(
+    transaction: &mut Transaction<'_, Sqlite>,
+    team: &str,
+    service: &str,
+    sync_data_list: &HashMap<String, SyncData>,
+    archive_time: u64,
+) -> Result<(), String> {
+    info!(
+        "archive team={} service={} count={}",
+        team,
+        service,
+        sync_data_list.len()
+    );
+    let query = "
+    INSERT OR REPLACE INTO sync_data_archive (team, service, id, status, server_update_time, data, archive_time)
+    VALUES (?, ?, ?, ?, ?, ?, ?)";
+    for (id, sync_data) in sync_data_list {
+        let data = serde_json::to_string(sync_data)
+            .map_err(|err| format!("Error serializing {}: {}", id, err))?;
+        sqlx::query(query)
+            .bind(team)
+            .bind(service)
+            .bind(id)
+            .bind(sync_data.status.to_string())
+            .bind(sync_data.server_update_time as i64)
+            .bind(data)
+            .bind(archive_time as i64)
+            .execute(&mut **transaction)
+            .await
+            .map_err(|err| format!("Error archiving data for {}/{}: {}", team, service, err))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+
+use log::info;
+use roadwork_sync_lib::sync_data::SyncData;
+use serde::Serialize;
+use sqlx::{Row, Sqlite, Transaction};
+
+use crate::service::data_repository::DataRepository;
+
+/// A saved version of a team dataset
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct SnapshotInfo {
+    pub(crate) id: i64,
+    pub(crate) time: u64,
+    /// The user whose sync produced this version
+    pub(crate) username: String,
+    pub(crate) entries: u64,
+}
+
+/// A version of a dataset saved with the change producing it
+#[derive(Debug)]
+pub(crate) struct NewSnapshot {
+    /// The user whose sync produced this version
+    pub(crate) username: String,
+    pub(crate) time: u64,
+    pub(crate) sync_data_list: HashMap<String, SyncData>,
+    /// How many snapshots of the dataset are kept, 0 keeps them all
+    pub(crate) max_count: u64,
+    /// The older snapshots of the dataset are removed
+    pub(crate) min_time: u64,
+}
+
+/// Save a snapshot and remove the snapshots of the dataset over the count or too old
+pub(super) async fn insert_snapshot(
+    transaction: &mut Transaction<'_, Sqlite>,
+    team: &str,
+    service: &str,
+    snapshot: &NewSnapshot,
+) -> Result<(), String> {
+    info!("insert_snapshot team={} service={}", team, service);
+    let to_error =
+        |err: sqlx::Error| format!("Error saving snapshot of {}/{}: {}", team, service, err);
+    let data = serde_json::to_string(&snapshot.sync_data_list)
+        .map_err(|err| format!("Error serializing snapshot: {}", err))?;
+    sqlx::query(
+        "INSERT INTO dataset_snapshot (team, service, time, username, entries, data) VALUES (?, ?, ?, ?, ?, ?)",
+    )
+    .bind(team)
+    .bind(service)
+    .bind(snapshot.time as i64)
+    .bind(&snapshot.username)
+    .bind(snapshot.sync_data_list.len() as i64)
+    .bind(data)
+    .execute(&mut **transaction)
+    .await
+    .map_err(to_error)?;
+    sqlx::query("DELETE FROM dataset_snapshot WHERE team = ? AND service = ? AND time < ?")
+        .bind(team)
+        .bind(service)
+        .bind(snapshot.min_time as i64)
+        .execute(&mut **transaction)
+        .await
+        .map_err(to_error)?;
+    if snapshot.max_count > 0 {
+        let query = "
+        DELETE FROM dataset_snapshot
+        WHERE team = ? AND service = ? AND id NOT IN (
+            SELECT id FROM dataset_snapshot WHERE team = ? AND service = ? ORDER BY id DESC LIMIT ?
+        )";
+        sqlx::query(query)
+            .bind(team)
+            .bind(service)
+            .bind(team)
+            .bind(service)
+            .bind(snapshot.max_count as i64)
+            .execute(&mut **transaction)
+            .await
+            .map_err(to_error)?;
+    }
+    Ok(())
+}
+
+impl DataRepository {
+    /// The snapshots of a dataset, newest first
+    pub(crate) async fn list_snapshots(
+        &self,
+        team: &str,
+        service: &str,
+    ) -> Result<Vec<SnapshotInfo>, String> {
+        let query = "
+        SELECT id, time, username, entries FROM dataset_snapshot
+        WHERE team = ? AND service = ?
+        ORDER BY id DESC";
+        let rows = sqlx::query(query)
+            .bind(team)
+            .bind(service)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|err| format!("Error listing snapshots of {}/{}: {}", team, service, err))?;
+        Ok(rows
+            .iter()
+            .map(|row| SnapshotInfo {
+                id: row.get(0),
+                time: row.get::<i64, _>(1) as u64,
+                username: row.get(2),
+                entries: row.get::<i64, _>(3) as u64,
+            })
+            .collect())
+    }
+
+    /// The entries of a snapshot, None if the snapshot does not exist for this dataset
+    pub(crate) async fn load_snapshot(
+        &self,
+        team: &str,
+        service: &str,
+        id: i64,
+    ) -> Result<Option<HashMap<String, SyncData>>, String> {
+        let query = "SELECT data FROM dataset_snapshot WHERE team = ? AND service = ? AND id = ?";
+        let row = sqlx::query(query)
+            .bind(team)
+            .bind(service)
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|err| format!("Error loading snapshot {}: {}", id, err))?;
+        match row {
+            None => Ok(None),
+            Some(row) => serde_json::from_str(row.get::<&str, _>(0))
+                .map(Some)
+                .map_err(|err| format!("Error reading snapshot {}: {}", id, err)),
+        }
+    }
+}
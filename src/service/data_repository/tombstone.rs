@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+
+use log::info;
+use roadwork_sync_lib::sync_data::SyncData;
+use sqlx::{Row, Sqlite, Transaction};
+
+use crate::service::data_repository::{DataRepository, StatusChange, history, retention};
+
+/// Keep a tombstone for each deleted entry so the deletion reaches the other clients
+pub(super) async fn insert_tombstones(
+    transaction: &mut Transaction<'_, Sqlite>,
+    team: &str,
+    service: &str,
+    ids: &[String],
+    deletion_time: u64,
+) -> Result<(), String> {
+    for id in ids {
+        sqlx::query(
+            "INSERT OR REPLACE INTO sync_data_tombstone (team, service, id, deletion_time) VALUES (?, ?, ?, ?)",
+        )
+        .bind(team)
+        .bind(service)
+        .bind(id)
+        .bind(deletion_time as i64)
+        .execute(&mut **transaction)
+        .await
+        .map_err(|err| format!("Error deleting data for {}/{}: {}", team, service, err))?;
+    }
+    Ok(())
+}
+
+/// Remove the tombstones of entries that exist again
+pub(super) async fn delete_tombstones<'a>(
+    transaction: &mut Transaction<'_, Sqlite>,
+    team: &str,
+    service: &str,
+    ids: impl IntoIterator<Item = &'a String>,
+) -> Result<(), String> {
+    for id in ids {
+        sqlx::query("DELETE FROM sync_data_tombstone WHERE team = ? AND service = ? AND id = ?")
+            .bind(team)
+            .bind(service)
+            .bind(id)
+            .execute(&mut **transaction)
+            .await
+            .map_err(|err| format!("Error removing tombstones of {}/{}: {}", team, service, err))?;
+    }
+    Ok(())
+}
+
+impl DataRepository {
+    /// Delete entries of a dataset and keep a tombstone so the deletion reaches the other clients.
+    /// The tombstones and the history are written in the same transaction as the deletion,
+    /// see DataStore for the stores outside the database.
+    /// The archived entries are copied to the archive table in the same transaction
+    pub(crate) async fn delete(
+        &self,
+        team: &str,
+        service: &str,
+        ids: &[String],
+        deletion_time: u64,
+        status_changes: &[StatusChange],
+        archived_sync_data_list: &HashMap<String, SyncData>,
+    ) -> Result<(), String> {
+        info!("delete team={} service={} ids={:?}", team, service, ids);
+        let to_error =
+            |err: sqlx::Error| format!("Error deleting data for {}/{}: {}", team, service, err);
+        let mut transaction = self.pool.begin().await.map_err(to_error)?;
+        if !archived_sync_data_list.is_empty() {
+            retention::insert_archive(
+                &mut transaction,
+                team,
+                service,
+                archived_sync_data_list,
+                deletion_time,
+            )
+            .await?;
+        }
+        self.data_store
+            .delete(&mut transaction, team, service, ids)
+            .await?;
+        insert_tombstones(&mut transaction, team, service, ids, deletion_time).await?;
+        history::insert_history(&mut transaction, status_changes)
+            .await
+            .map_err(to_error)?;
+        transaction.commit().await.map_err(to_error)
+    }
+
+    /// The deleted ids of a dataset with their deletion time, deleted at or after the given time
+    pub(crate) async fn load_tombstones(
+        &self,
+        team: &str,
+        service: &str,
+        since: u64,
+    ) -> Result<HashMap<String, u64>, String> {
+        let query = "SELECT id, deletion_time FROM sync_data_tombstone WHERE team = ? AND service = ? AND deletion_time >= ?";
+        let rows = sqlx::query(query)
+            .bind(team)
+            .bind(service)
+            .bind(since as i64)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|err| format!("Error loading tombstones for {}/{}: {}", team, service, err))?;
+        Ok(rows
+            .iter()
+            .map(|row| (row.get(0), row.get::<i64, _>(1) as u64))
+            .collect())
+    }
+
+    /// Remove the tombstones older than the given time, returns the number of removed tombstones
+    pub(crate) async fn purge_tombstones(&self, before: u64) -> Result<u64, String> {
+        info!("purge_tombstones before={}", before);
+        sqlx::query("DELETE FROM sync_data_tombstone WHERE deletion_time < ?")
+            .bind(before as i64)
+            .execute(&self.pool)
+            .await
+            .map(|result| result.rows_affected())
+            .map_err(|err| format!("Error purging tombstones: {}", err))
+    }
+}
@@ -0,0 +1,231 @@
+use log::info;
+use serde::{Deserialize, Serialize};
+use sqlx::Row;
+use sqlx::sqlite::SqliteRow;
+
+use crate::service::data::now;
+use crate::service::data_repository::DataRepository;
+
+/// A team webhook, called for each status change of the team datasets
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Webhook {
+    /// Given by the server when the webhook is registered
+    #[serde(default)]
+    pub(crate) id: i64,
+    pub(crate) team: String,
+    pub(crate) url: String,
+    /// The key signing the events, it is never returned
+    #[serde(skip_serializing)]
+    pub(crate) secret: String,
+    /// The opendata services the webhook is interested in, all of them if empty
+    #[serde(default)]
+    pub(crate) services: Vec<String>,
+}
+
+impl Webhook {
+    pub(crate) fn accepts(&self, service: &str) -> bool {
+        self.services.is_empty() || self.services.iter().any(|accepted| accepted == service)
+    }
+}
+
+/// One attempt to deliver an event to a webhook.
+/// A queued event is recorded as attempt 0, pending until it is delivered or given up,
+/// so it is delivered after a restart
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct WebhookDelivery {
+    pub(crate) id: i64,
+    pub(crate) webhook_id: i64,
+    pub(crate) event: String,
+    pub(crate) attempt: u32,
+    pub(crate) time: u64,
+    /// The HTTP status of the response, missing if the webhook could not be reached
+    pub(crate) status_code: Option<u16>,
+    pub(crate) error: Option<String>,
+    pub(crate) pending: bool,
+}
+
+impl DataRepository {
+    pub(crate) async fn list_webhooks(&self) -> Result<Vec<Webhook>, String> {
+        info!("list_webhooks");
+        let query = "SELECT id, team, url, secret, services FROM webhook ORDER BY team, id";
+        let rows = sqlx::query(query)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|err| format!("Error listing webhooks: {}", err))?;
+        Ok(rows.iter().map(read_webhook).collect())
+    }
+
+    /// The webhooks of a team interested in an opendata service
+    pub(crate) async fn find_webhooks(
+        &self,
+        team: &str,
+        service: &str,
+    ) -> Result<Vec<Webhook>, String> {
+        let query = "SELECT id, team, url, secret, services FROM webhook WHERE team = ?";
+        let rows = sqlx::query(query)
+            .bind(team)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|err| format!("Error finding webhooks of {}: {}", team, err))?;
+        Ok(rows
+            .iter()
+            .map(read_webhook)
+            .filter(|webhook| webhook.accepts(service))
+            .collect())
+    }
+
+    pub(crate) async fn find_webhook(&self, id: i64) -> Result<Option<Webhook>, String> {
+        let query = "SELECT id, team, url, secret, services FROM webhook WHERE id = ?";
+        let row = sqlx::query(query)
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|err| format!("Error finding webhook {}: {}", id, err))?;
+        Ok(row.as_ref().map(read_webhook))
+    }
+
+    /// Register a webhook, returns its id
+    pub(crate) async fn insert_webhook(&self, webhook: &Webhook) -> Result<i64, String> {
+        info!("insert_webhook team={} url={}", webhook.team, webhook.url);
+        let query = "INSERT INTO webhook (team, url, secret, services) VALUES (?, ?, ?, ?)";
+        sqlx::query(query)
+            .bind(&webhook.team)
+            .bind(&webhook.url)
+            .bind(&webhook.secret)
+            .bind(webhook.services.join(","))
+            .execute(&self.pool)
+            .await
+            .map(|result| result.last_insert_rowid())
+            .map_err(|err| format!("Error inserting webhook for {}: {}", webhook.team, err))
+    }
+
+    /// Remove a webhook and its delivery log
+    pub(crate) async fn delete_webhook(&self, id: i64) -> Result<(), String> {
+        info!("delete_webhook id={}", id);
+        let to_error = |err: sqlx::Error| format!("Error deleting webhook {}: {}", id, err);
+        let mut transaction = self.pool.begin().await.map_err(to_error)?;
+        for query in [
+            "DELETE FROM webhook_delivery WHERE webhook_id = ?",
+            "DELETE FROM webhook WHERE id = ?",
+        ] {
+            sqlx::query(query)
+                .bind(id)
+                .execute(&mut *transaction)
+                .await
+                .map_err(to_error)?;
+        }
+        transaction.commit().await.map_err(to_error)
+    }
+
+    /// Log a delivery attempt, returns its id
+    pub(crate) async fn insert_webhook_delivery(
+        &self,
+        delivery: &WebhookDelivery,
+    ) -> Result<i64, String> {
+        let query = "INSERT INTO webhook_delivery (webhook_id, event, attempt, time, status_code, error, pending) VALUES (?, ?, ?, ?, ?, ?, ?)";
+        sqlx::query(query)
+            .bind(delivery.webhook_id)
+            .bind(&delivery.event)
+            .bind(delivery.attempt)
+            .bind(delivery.time as i64)
+            .bind(delivery.status_code)
+            .bind(&delivery.error)
+            .bind(delivery.pending)
+            .execute(&self.pool)
+            .await
+            .map(|result| result.last_insert_rowid())
+            .map_err(|err| {
+                format!(
+                    "Error logging delivery to webhook {}: {}",
+                    delivery.webhook_id, err
+                )
+            })
+    }
+
+    /// Record the outcome of a queued event, it is no longer pending
+    pub(crate) async fn finish_webhook_delivery(
+        &self,
+        id: i64,
+        status_code: Option<u16>,
+        error: &Option<String>,
+    ) -> Result<(), String> {
+        let query = "UPDATE webhook_delivery SET pending = 0, time = ?, status_code = ?, error = ? WHERE id = ?";
+        sqlx::query(query)
+            .bind(now() as i64)
+            .bind(status_code)
+            .bind(error)
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map(|_| ())
+            .map_err(|err| format!("Error finishing webhook delivery {}: {}", id, err))
+    }
+
+    /// The queued events of all the webhooks, oldest first
+    pub(crate) async fn list_pending_webhook_deliveries(
+        &self,
+    ) -> Result<Vec<WebhookDelivery>, String> {
+        let query = "
+        SELECT id, webhook_id, event, attempt, time, status_code, error, pending FROM webhook_delivery
+        WHERE pending = 1
+        ORDER BY id";
+        let rows = sqlx::query(query)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|err| format!("Error listing pending webhook deliveries: {}", err))?;
+        Ok(rows.iter().map(read_webhook_delivery).collect())
+    }
+
+    /// The latest delivery attempts of a webhook, newest first
+    pub(crate) async fn list_webhook_deliveries(
+        &self,
+        webhook_id: i64,
+        limit: u32,
+    ) -> Result<Vec<WebhookDelivery>, String> {
+        let query = "
+        SELECT id, webhook_id, event, attempt, time, status_code, error, pending FROM webhook_delivery
+        WHERE webhook_id = ?
+        ORDER BY id DESC
+        LIMIT ?";
+        let rows = sqlx::query(query)
+            .bind(webhook_id)
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|err| {
+                format!(
+                    "Error listing deliveries of webhook {}: {}",
+                    webhook_id, err
+                )
+            })?;
+        Ok(rows.iter().map(read_webhook_delivery).collect())
+    }
+}
+
+fn read_webhook_delivery(row: &SqliteRow) -> WebhookDelivery {
+    WebhookDelivery {
+        id: row.get(0),
+        webhook_id: row.get(1),
+        event: row.get(2),
+        attempt: row.get(3),
+        time: row.get::<i64, _>(4) as u64,
+        status_code: row.get(5),
+        error: row.get(6),
+        pending: row.get(7),
+    }
+}
+
+fn read_webhook(row: &SqliteRow) -> Webhook {
+    let services: String = row.get(4);
+    Webhook {
+        id: row.get(0),
+        team: row.get(1),
+        url: row.get(2),
+        secret: row.get(3),
+        services: services
+            .split(',')
+            .filter(|service| !service.is_empty())
+            .map(str::to_string)
+            .collect(),
+    }
+}
@@ -0,0 +1,232 @@
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use log::{info, warn};
+use roadwork_sync_lib::sync_data::SyncData;
+use serde_json::Value;
+use sqlx::{Sqlite, Transaction};
+use tokio::fs;
+use tokio::sync::Mutex;
+
+use crate::service::data::now;
+use crate::service::data_store::{CorruptEntry, DataStore, StoredDataset, StoredDatasetInfo};
+
+/// The entries are stored in {data_dir}/{team}/{service}.json, the format of the legacy files
+pub(crate) struct FileDataStore {
+    data_dir: PathBuf,
+    /// Serializes the read-modify-write of the files
+    write_lock: Mutex<()>,
+}
+
+impl FileDataStore {
+    pub(crate) fn new(data_dir: &str) -> Self {
+        FileDataStore {
+            data_dir: PathBuf::from(data_dir),
+            write_lock: Mutex::new(()),
+        }
+    }
+
+    /// The team and the service are refused if they are not a single path segment
+    fn path(&self, team: &str, service: &str) -> Result<PathBuf, String> {
+        check_path_segment(team)?;
+        check_path_segment(service)?;
+        Ok(self.data_dir.join(team).join(format!("{}.json", service)))
+    }
+
+    /// The raw entries of a dataset file, None if the file is missing
+    async fn read(&self, path: &Path) -> Result<Option<HashMap<String, Value>>, String> {
+        let content = match fs::read_to_string(path).await {
+            Ok(content) => content,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(format!("Error reading {}: {}", path.display(), err)),
+        };
+        serde_json::from_str(&content)
+            .map(Some)
+            .map_err(|err| format!("Error reading {}: {}", path.display(), err))
+    }
+
+    /// Write to a temporary file renamed over the dataset file, so a crash never leaves it half written
+    async fn write(&self, path: &Path, entries: &HashMap<String, Value>) -> Result<(), String> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .await
+                .map_err(|err| format!("Error creating {}: {}", parent.display(), err))?;
+        }
+        let content = serde_json::to_string(entries)
+            .map_err(|err| format!("Error serializing {}: {}", path.display(), err))?;
+        let tmp_path = path.with_extension("json.tmp");
+        fs::write(&tmp_path, content)
+            .await
+            .map_err(|err| format!("Error writing {}: {}", tmp_path.display(), err))?;
+        fs::rename(&tmp_path, path)
+            .await
+            .map_err(|err| format!("Error renaming {}: {}", tmp_path.display(), err))
+    }
+}
+
+#[async_trait]
+impl DataStore for FileDataStore {
+    /// A file that cannot be parsed is renamed to {service}.json.corrupted-{time}
+    /// and reported as one corrupt entry named after the renamed file
+    async fn load(&self, team: &str, service: &str) -> Result<StoredDataset, String> {
+        info!("load team={} service={}", team, service);
+        let path = self.path(team, service)?;
+        let _guard = self.write_lock.lock().await;
+        let mut stored_dataset = StoredDataset::default();
+        let raw_entries = match self.read(&path).await {
+            Ok(Some(raw_entries)) => raw_entries,
+            Ok(None) => return Ok(stored_dataset),
+            Err(err) => {
+                warn!("{}", err);
+                let corrupted_path = path.with_extension(format!("json.corrupted-{}", now()));
+                fs::rename(&path, &corrupted_path)
+                    .await
+                    .map_err(|err| format!("Error renaming {}: {}", path.display(), err))?;
+                stored_dataset.corrupt_entries.push(CorruptEntry {
+                    id: corrupted_path.to_string_lossy().to_string(),
+                    data: String::new(),
+                    error: err,
+                });
+                return Ok(stored_dataset);
+            }
+        };
+        for (id, value) in raw_entries {
+            match serde_json::from_value::<SyncData>(value.clone()) {
+                Ok(sync_data) => {
+                    stored_dataset.entries.insert(id, sync_data);
+                }
+                Err(err) => {
+                    warn!("Unable to read {}/{}/{}: {}", team, service, id, err);
+                    stored_dataset.corrupt_entries.push(CorruptEntry {
+                        id,
+                        data: value.to_string(),
+                        error: err.to_string(),
+                    });
+                }
+            }
+        }
+        Ok(stored_dataset)
+    }
+
+    /// The entries are written before the transaction is committed by the caller
+    async fn save(
+        &self,
+        _transaction: &mut Transaction<'_, Sqlite>,
+        team: &str,
+        service: &str,
+        sync_data_list: &HashMap<String, SyncData>,
+    ) -> Result<(), String> {
+        info!(
+            "save team={} service={} entries={}",
+            team,
+            service,
+            sync_data_list.len()
+        );
+        if sync_data_list.is_empty() {
+            return Ok(());
+        }
+        let path = self.path(team, service)?;
+        let _guard = self.write_lock.lock().await;
+        // a file that cannot be read is never overwritten, the load quarantines it first
+        let mut raw_entries = self.read(&path).await?.unwrap_or_default();
+        for (id, sync_data) in sync_data_list {
+            let value = serde_json::to_value(sync_data)
+                .map_err(|err| format!("Error serializing {}: {}", id, err))?;
+            raw_entries.insert(id.clone(), value);
+        }
+        self.write(&path, &raw_entries).await
+    }
+
+    async fn list(&self) -> Result<Vec<StoredDatasetInfo>, String> {
+        let mut stored_datasets = Vec::new();
+        let mut team_dirs = match fs::read_dir(&self.data_dir).await {
+            Ok(team_dirs) => team_dirs,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(stored_datasets),
+            Err(err) => {
+                return Err(format!(
+                    "Error listing {}: {}",
+                    self.data_dir.display(),
+                    err
+                ));
+            }
+        };
+        while let Ok(Some(team_dir)) = team_dirs.next_entry().await {
+            let team = team_dir.file_name().to_string_lossy().to_string();
+            stored_datasets.extend(self.list_team(&team).await?);
+        }
+        stored_datasets.sort_by(|dataset1, dataset2| {
+            (&dataset1.team, &dataset1.service).cmp(&(&dataset2.team, &dataset2.service))
+        });
+        Ok(stored_datasets)
+    }
+
+    /// Only the files of the team directory are read
+    async fn list_team(&self, team: &str) -> Result<Vec<StoredDatasetInfo>, String> {
+        check_path_segment(team)?;
+        let mut stored_datasets = Vec::new();
+        let Ok(mut files) = fs::read_dir(self.data_dir.join(team)).await else {
+            return Ok(stored_datasets);
+        };
+        while let Ok(Some(file)) = files.next_entry().await {
+            let path = file.path();
+            if path.extension().is_none_or(|extension| extension != "json") {
+                continue;
+            }
+            let Some(service) = path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+            else {
+                continue;
+            };
+            let entries = match self.read(&path).await {
+                Ok(raw_entries) => raw_entries.map_or(0, |raw_entries| raw_entries.len()),
+                Err(err) => {
+                    warn!("{}", err);
+                    0
+                }
+            };
+            if entries > 0 {
+                stored_datasets.push(StoredDatasetInfo {
+                    team: team.to_string(),
+                    service,
+                    entries: entries as u64,
+                });
+            }
+        }
+        stored_datasets.sort_by(|dataset1, dataset2| dataset1.service.cmp(&dataset2.service));
+        Ok(stored_datasets)
+    }
+
+    /// The entries are removed before the transaction is committed by the caller
+    async fn delete(
+        &self,
+        _transaction: &mut Transaction<'_, Sqlite>,
+        team: &str,
+        service: &str,
+        ids: &[String],
+    ) -> Result<(), String> {
+        let path = self.path(team, service)?;
+        let _guard = self.write_lock.lock().await;
+        let Some(mut raw_entries) = self.read(&path).await? else {
+            return Ok(());
+        };
+        let count = raw_entries.len();
+        ids.iter().for_each(|id| {
+            raw_entries.remove(id);
+        });
+        if raw_entries.len() == count {
+            return Ok(());
+        }
+        self.write(&path, &raw_entries).await
+    }
+}
+
+/// A name used as a file or directory name must not reach outside of the data directory
+fn check_path_segment(name: &str) -> Result<(), String> {
+    if name.is_empty() || name.contains(['/', '\\', '\0']) || name.contains("..") {
+        return Err(format!("Invalid data file name {:?}", name));
+    }
+    Ok(())
+}
@@ -0,0 +1,86 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use roadwork_sync_lib::sync_data::SyncData;
+use sqlx::{Sqlite, Transaction};
+
+use crate::service::data_store::{DataStore, StoredDataset, StoredDatasetInfo};
+
+/// The datasets by (team, service)
+type Datasets = HashMap<(String, String), HashMap<String, SyncData>>;
+
+/// The entries are kept in memory only, they are lost when the server stops
+#[derive(Default)]
+pub(crate) struct MemoryDataStore {
+    datasets: Mutex<Datasets>,
+}
+
+#[async_trait]
+impl DataStore for MemoryDataStore {
+    async fn load(&self, team: &str, service: &str) -> Result<StoredDataset, String> {
+        let datasets = self.datasets.lock().unwrap();
+        Ok(StoredDataset {
+            entries: datasets
+                .get(&(team.to_string(), service.to_string()))
+                .cloned()
+                .unwrap_or_default(),
+            corrupt_entries: Vec::new(),
+        })
+    }
+
+    async fn save(
+        &self,
+        _transaction: &mut Transaction<'_, Sqlite>,
+        team: &str,
+        service: &str,
+        sync_data_list: &HashMap<String, SyncData>,
+    ) -> Result<(), String> {
+        if sync_data_list.is_empty() {
+            return Ok(());
+        }
+        let mut datasets = self.datasets.lock().unwrap();
+        datasets
+            .entry((team.to_string(), service.to_string()))
+            .or_default()
+            .extend(
+                sync_data_list
+                    .iter()
+                    .map(|(id, sync_data)| (id.clone(), sync_data.clone())),
+            );
+        Ok(())
+    }
+
+    async fn list(&self) -> Result<Vec<StoredDatasetInfo>, String> {
+        let datasets = self.datasets.lock().unwrap();
+        let mut stored_datasets: Vec<StoredDatasetInfo> = datasets
+            .iter()
+            .filter(|(_, entries)| !entries.is_empty())
+            .map(|((team, service), entries)| StoredDatasetInfo {
+                team: team.clone(),
+                service: service.clone(),
+                entries: entries.len() as u64,
+            })
+            .collect();
+        stored_datasets.sort_by(|dataset1, dataset2| {
+            (&dataset1.team, &dataset1.service).cmp(&(&dataset2.team, &dataset2.service))
+        });
+        Ok(stored_datasets)
+    }
+
+    async fn delete(
+        &self,
+        _transaction: &mut Transaction<'_, Sqlite>,
+        team: &str,
+        service: &str,
+        ids: &[String],
+    ) -> Result<(), String> {
+        let mut datasets = self.datasets.lock().unwrap();
+        if let Some(entries) = datasets.get_mut(&(team.to_string(), service.to_string())) {
+            ids.iter().for_each(|id| {
+                entries.remove(id);
+            });
+        }
+        Ok(())
+    }
+}
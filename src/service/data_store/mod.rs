@@ -0,0 +1,343 @@
+mod file;
+mod memory;
+mod sqlite;
+
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use log::info;
+use roadwork_sync_lib::sync_data::SyncData;
+use sqlx::{Pool, Sqlite, Transaction};
+
+use crate::config::Config;
+
+pub(crate) use file::FileDataStore;
+pub(crate) use memory::MemoryDataStore;
+pub(crate) use sqlite::SqliteDataStore;
+
+/// Where the entries of the team datasets are stored.
+/// The other data (history, tombstones, snapshots...) stays in the database.
+///
+/// The writes receive the database transaction holding the history and tombstones of the change.
+/// The SQLite store writes the entries in it, so everything is committed together.
+/// The filesystem and memory stores write immediately, the caller commits the transaction only
+/// once the write succeeded: the history never records a change that was not stored,
+/// but a failed commit leaves the stored change without its history and tombstones
+#[async_trait]
+pub(crate) trait DataStore: Send + Sync {
+    /// Load a dataset, the entries that cannot be read are returned apart
+    async fn load(&self, team: &str, service: &str) -> Result<StoredDataset, String>;
+
+    /// Load the entries of a dataset modified at or after the given server time
+    async fn load_since(
+        &self,
+        team: &str,
+        service: &str,
+        since: u64,
+    ) -> Result<StoredDataset, String> {
+        let mut stored_dataset = self.load(team, service).await?;
+        stored_dataset
+            .entries
+            .retain(|_, sync_data| sync_data.server_update_time >= since);
+        Ok(stored_dataset)
+    }
+
+    /// Insert or update the given entries, other entries of the dataset are left untouched
+    async fn save(
+        &self,
+        transaction: &mut Transaction<'_, Sqlite>,
+        team: &str,
+        service: &str,
+        sync_data_list: &HashMap<String, SyncData>,
+    ) -> Result<(), String>;
+
+    /// The datasets having entries
+    async fn list(&self) -> Result<Vec<StoredDatasetInfo>, String>;
+
+    /// The datasets of a team having entries
+    async fn list_team(&self, team: &str) -> Result<Vec<StoredDatasetInfo>, String> {
+        let mut stored_datasets = self.list().await?;
+        stored_datasets.retain(|stored_dataset| stored_dataset.team == team);
+        Ok(stored_datasets)
+    }
+
+    async fn delete(
+        &self,
+        transaction: &mut Transaction<'_, Sqlite>,
+        team: &str,
+        service: &str,
+        ids: &[String],
+    ) -> Result<(), String>;
+}
+
+/// The entries of a dataset as found in the store
+#[derive(Debug, Default)]
+pub(crate) struct StoredDataset {
+    pub(crate) entries: HashMap<String, SyncData>,
+    pub(crate) corrupt_entries: Vec<CorruptEntry>,
+}
+
+/// A stored entry that cannot be deserialized
+#[derive(Debug)]
+pub(crate) struct CorruptEntry {
+    pub(crate) id: String,
+    pub(crate) data: String,
+    pub(crate) error: String,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct StoredDatasetInfo {
+    pub(crate) team: String,
+    pub(crate) service: String,
+    pub(crate) entries: u64,
+}
+
+/// The data store implementations, chosen by ROADWORK_DATA_STORE
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DataStoreKind {
+    Sqlite,
+    Filesystem,
+    /// Nothing survives a restart, for tests
+    Memory,
+}
+
+impl FromStr for DataStoreKind {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "sqlite" => Ok(DataStoreKind::Sqlite),
+            "filesystem" => Ok(DataStoreKind::Filesystem),
+            "memory" => Ok(DataStoreKind::Memory),
+            _ => Err(format!("Unknown data store {}", name)),
+        }
+    }
+}
+
+/// Open the data store selected by the configuration
+pub(crate) async fn open(
+    config: &Config,
+    pool: Pool<Sqlite>,
+) -> Result<Arc<dyn DataStore>, sqlx::Error> {
+    info!("open data store {:?}", config.data_store);
+    let data_store: Arc<dyn DataStore> = match config.data_store {
+        DataStoreKind::Sqlite => Arc::new(SqliteDataStore::new(pool).await?),
+        DataStoreKind::Filesystem => Arc::new(FileDataStore::new(&config.data_dir)),
+        DataStoreKind::Memory => Arc::new(MemoryDataStore::default()),
+    };
+    Ok(data_store)
+}
+
+#[cfg(test)]
+mod tests {
+    use roadwork_sync_lib::sync_data::Status;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    use super::*;
+
+    async fn memory_pool() -> Pool<Sqlite> {
+        SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap()
+    }
+
+    fn sync_data(server_update_time: u64, status: Status) -> SyncData {
+        SyncData {
+            local_update_time: server_update_time,
+            server_update_time,
+            status,
+            dirty: false,
+        }
+    }
+
+    async fn save(
+        data_store: &dyn DataStore,
+        pool: &Pool<Sqlite>,
+        team: &str,
+        sync_data_list: HashMap<String, SyncData>,
+    ) {
+        let mut transaction = pool.begin().await.unwrap();
+        data_store
+            .save(&mut transaction, team, "service", &sync_data_list)
+            .await
+            .unwrap();
+        transaction.commit().await.unwrap();
+    }
+
+    /// Load, save, list and delete, the same expectations for every store
+    async fn check_data_store(data_store: &dyn DataStore, pool: &Pool<Sqlite>) {
+        assert!(
+            data_store
+                .load("team1", "service")
+                .await
+                .unwrap()
+                .entries
+                .is_empty()
+        );
+        assert!(data_store.list().await.unwrap().is_empty());
+
+        save(
+            data_store,
+            pool,
+            "team1",
+            HashMap::from([
+                ("1".to_string(), sync_data(10, Status::New)),
+                ("2".to_string(), sync_data(20, Status::Ongoing)),
+            ]),
+        )
+        .await;
+        save(
+            data_store,
+            pool,
+            "team1",
+            HashMap::from([("2".to_string(), sync_data(30, Status::Finished))]),
+        )
+        .await;
+        save(
+            data_store,
+            pool,
+            "team2",
+            HashMap::from([("3".to_string(), sync_data(10, Status::New))]),
+        )
+        .await;
+
+        let entries = data_store.load("team1", "service").await.unwrap().entries;
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries["1"].status, Status::New);
+        assert_eq!(entries["2"].status, Status::Finished);
+        assert_eq!(entries["2"].server_update_time, 30);
+
+        let entries = data_store
+            .load_since("team1", "service", 30)
+            .await
+            .unwrap()
+            .entries;
+        assert_eq!(entries.keys().collect::<Vec<_>>(), vec!["2"]);
+
+        let stored_datasets = data_store.list().await.unwrap();
+        assert_eq!(
+            stored_datasets
+                .iter()
+                .map(|dataset| (dataset.team.as_str(), dataset.entries))
+                .collect::<Vec<_>>(),
+            vec![("team1", 2), ("team2", 1)]
+        );
+        let stored_datasets = data_store.list_team("team2").await.unwrap();
+        assert_eq!(stored_datasets.len(), 1);
+        assert_eq!(stored_datasets[0].service, "service");
+
+        let mut transaction = pool.begin().await.unwrap();
+        data_store
+            .delete(&mut transaction, "team1", "service", &["1".to_string()])
+            .await
+            .unwrap();
+        transaction.commit().await.unwrap();
+        let entries = data_store.load("team1", "service").await.unwrap().entries;
+        assert_eq!(entries.keys().collect::<Vec<_>>(), vec!["2"]);
+    }
+
+    #[tokio::test]
+    async fn sqlite_data_store() {
+        let pool = memory_pool().await;
+        let data_store = SqliteDataStore::new(pool.clone()).await.unwrap();
+        check_data_store(&data_store, &pool).await;
+    }
+
+    #[tokio::test]
+    async fn sqlite_data_store_rollback() {
+        let pool = memory_pool().await;
+        let data_store = SqliteDataStore::new(pool.clone()).await.unwrap();
+        let mut transaction = pool.begin().await.unwrap();
+        data_store
+            .save(
+                &mut transaction,
+                "team",
+                "service",
+                &HashMap::from([("1".to_string(), sync_data(10, Status::New))]),
+            )
+            .await
+            .unwrap();
+        transaction.rollback().await.unwrap();
+        assert!(
+            data_store
+                .load("team", "service")
+                .await
+                .unwrap()
+                .entries
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn file_data_store() {
+        let data_dir =
+            std::env::temp_dir().join(format!("roadwork-test-{}", rand::random::<u64>()));
+        let pool = memory_pool().await;
+        let data_store = FileDataStore::new(&data_dir.to_string_lossy());
+        check_data_store(&data_store, &pool).await;
+        std::fs::remove_dir_all(data_dir).unwrap();
+    }
+
+    /// A failed write leaves the dataset as it was, the temporary file is not read
+    #[tokio::test]
+    async fn file_data_store_failed_write() {
+        let data_dir =
+            std::env::temp_dir().join(format!("roadwork-test-{}", rand::random::<u64>()));
+        let pool = memory_pool().await;
+        let data_store = FileDataStore::new(&data_dir.to_string_lossy());
+        save(
+            &data_store,
+            &pool,
+            "team",
+            HashMap::from([("1".to_string(), sync_data(10, Status::New))]),
+        )
+        .await;
+
+        // the temporary file cannot be written over a directory
+        let tmp_path = data_dir.join("team").join("service.json.tmp");
+        std::fs::create_dir(&tmp_path).unwrap();
+        let mut transaction = pool.begin().await.unwrap();
+        assert!(
+            data_store
+                .save(
+                    &mut transaction,
+                    "team",
+                    "service",
+                    &HashMap::from([("2".to_string(), sync_data(20, Status::New))]),
+                )
+                .await
+                .is_err()
+        );
+        let entries = data_store.load("team", "service").await.unwrap().entries;
+        assert_eq!(entries.keys().collect::<Vec<_>>(), vec!["1"]);
+
+        // a temporary file left by an interrupted write
+        std::fs::remove_dir(&tmp_path).unwrap();
+        std::fs::write(&tmp_path, "{\"2\": garbage").unwrap();
+        let stored_dataset = data_store.load("team", "service").await.unwrap();
+        assert_eq!(stored_dataset.entries.keys().collect::<Vec<_>>(), vec!["1"]);
+        assert!(stored_dataset.corrupt_entries.is_empty());
+        assert_eq!(data_store.list_team("team").await.unwrap().len(), 1);
+        std::fs::remove_dir_all(data_dir).unwrap();
+    }
+
+    /// The names reaching outside of the data directory are refused
+    #[tokio::test]
+    async fn file_data_store_path() {
+        let data_store = FileDataStore::new("data");
+        for (team, service) in [("..", "service"), ("team", "../service"), ("team", "a\\b")] {
+            assert!(data_store.load(team, service).await.is_err());
+        }
+        assert!(data_store.list_team("../team").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn memory_data_store() {
+        let pool = memory_pool().await;
+        check_data_store(&MemoryDataStore::default(), &pool).await;
+    }
+}
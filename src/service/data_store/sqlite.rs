@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use log::{info, warn};
+use roadwork_sync_lib::sync_data::SyncData;
+use sqlx::sqlite::SqliteRow;
+use sqlx::{Executor, Pool, Row, Sqlite, Transaction};
+
+use crate::service::data_store::{CorruptEntry, DataStore, StoredDataset, StoredDatasetInfo};
+
+/// The entries are stored in the sync_data table, one row per entry
+pub(crate) struct SqliteDataStore {
+    pool: Pool<Sqlite>,
+}
+
+impl SqliteDataStore {
+    pub(crate) async fn new(pool: Pool<Sqlite>) -> Result<Self, sqlx::Error> {
+        let query = "
+        CREATE TABLE IF NOT EXISTS sync_data (
+            team TEXT NOT NULL,
+            service TEXT NOT NULL,
+            id TEXT NOT NULL,
+            status TEXT NOT NULL,
+            server_update_time INTEGER NOT NULL,
+            data TEXT NOT NULL,
+            PRIMARY KEY (team, service, id)
+        );
+        CREATE INDEX IF NOT EXISTS sync_data_server_update_time
+            ON sync_data (team, service, server_update_time);
+    ";
+        pool.execute(query).await?;
+        Ok(SqliteDataStore { pool })
+    }
+}
+
+#[async_trait]
+impl DataStore for SqliteDataStore {
+    async fn load(&self, team: &str, service: &str) -> Result<StoredDataset, String> {
+        info!("load team={} service={}", team, service);
+        let query = "SELECT id, data FROM sync_data WHERE team = ? AND service = ?";
+        let rows = sqlx::query(query)
+            .bind(team)
+            .bind(service)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|err| format!("Error loading data for {}/{}: {}", team, service, err))?;
+        Ok(stored_dataset(team, service, rows))
+    }
+
+    /// Only the modified rows are read, with the sync_data_server_update_time index
+    async fn load_since(
+        &self,
+        team: &str,
+        service: &str,
+        since: u64,
+    ) -> Result<StoredDataset, String> {
+        info!(
+            "load_since team={} service={} since={}",
+            team, service, since
+        );
+        let query = "SELECT id, data FROM sync_data WHERE team = ? AND service = ? AND server_update_time >= ?";
+        let rows = sqlx::query(query)
+            .bind(team)
+            .bind(service)
+            .bind(since as i64)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|err| format!("Error loading data for {}/{}: {}", team, service, err))?;
+        Ok(stored_dataset(team, service, rows))
+    }
+
+    async fn save(
+        &self,
+        transaction: &mut Transaction<'_, Sqlite>,
+        team: &str,
+        service: &str,
+        sync_data_list: &HashMap<String, SyncData>,
+    ) -> Result<(), String> {
+        info!(
+            "save team={} service={} entries={}",
+            team,
+            service,
+            sync_data_list.len()
+        );
+        let query = "
+        INSERT INTO sync_data (team, service, id, status, server_update_time, data)
+        VALUES (?, ?, ?, ?, ?, ?)
+        ON CONFLICT (team, service, id) DO UPDATE SET
+            status = excluded.status,
+            server_update_time = excluded.server_update_time,
+            data = excluded.data";
+        let to_error =
+            |err: sqlx::Error| format!("Error saving data for {}/{}: {}", team, service, err);
+        for (id, sync_data) in sync_data_list {
+            let data = serde_json::to_string(sync_data)
+                .map_err(|err| format!("Error serializing {}: {}", id, err))?;
+            sqlx::query(query)
+                .bind(team)
+                .bind(service)
+                .bind(id)
+                .bind(sync_data.status.to_string())
+                .bind(sync_data.server_update_time as i64)
+                .bind(data)
+                .execute(&mut **transaction)
+                .await
+                .map_err(to_error)?;
+        }
+        Ok(())
+    }
+
+    async fn list(&self) -> Result<Vec<StoredDatasetInfo>, String> {
+        let query = "SELECT team, service, COUNT(*) FROM sync_data GROUP BY team, service ORDER BY team, service";
+        let rows = sqlx::query(query)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|err| format!("Error listing datasets: {}", err))?;
+        Ok(rows.iter().map(stored_dataset_info).collect())
+    }
+
+    async fn list_team(&self, team: &str) -> Result<Vec<StoredDatasetInfo>, String> {
+        let query = "SELECT team, service, COUNT(*) FROM sync_data WHERE team = ? GROUP BY service ORDER BY service";
+        let rows = sqlx::query(query)
+            .bind(team)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|err| format!("Error listing datasets of {}: {}", team, err))?;
+        Ok(rows.iter().map(stored_dataset_info).collect())
+    }
+
+    async fn delete(
+        &self,
+        transaction: &mut Transaction<'_, Sqlite>,
+        team: &str,
+        service: &str,
+        ids: &[String],
+    ) -> Result<(), String> {
+        let to_error =
+            |err: sqlx::Error| format!("Error deleting data for {}/{}: {}", team, service, err);
+        for id in ids {
+            sqlx::query("DELETE FROM sync_data WHERE team = ? AND service = ? AND id = ?")
+                .bind(team)
+                .bind(service)
+                .bind(id)
+                .execute(&mut **transaction)
+                .await
+                .map_err(to_error)?;
+        }
+        Ok(())
+    }
+}
+
+/// The entries of the (id, data) rows, the rows that cannot be deserialized are returned apart
+fn stored_dataset(team: &str, service: &str, rows: Vec<SqliteRow>) -> StoredDataset {
+    let mut stored_dataset = StoredDataset::default();
+    for row in rows {
+        let id: String = row.get(0);
+        let data: String = row.get(1);
+        match serde_json::from_str::<SyncData>(&data) {
+            Ok(sync_data) => {
+                stored_dataset.entries.insert(id, sync_data);
+            }
+            Err(err) => {
+                warn!("Unable to read {}/{}/{}: {}", team, service, id, err);
+                stored_dataset.corrupt_entries.push(CorruptEntry {
+                    id,
+                    data,
+                    error: err.to_string(),
+                });
+            }
+        }
+    }
+    stored_dataset
+}
+
+fn stored_dataset_info(row: &SqliteRow) -> StoredDatasetInfo {
+    StoredDatasetInfo {
+        team: row.get(0),
+        service: row.get(1),
+        entries: row.get::<i64, _>(2) as u64,
+    }
+}
@@ -0,0 +1,156 @@
+use std::collections::HashMap;
+
+use roadwork_sync_lib::sync_data::SyncData;
+use serde_json::{Value, json};
+
+use crate::service::data_repository::Attribution;
+
+/// The formats a dataset can be exported to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ExportFormat {
+    Csv,
+    GeoJson,
+}
+
+impl ExportFormat {
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "csv" => Some(ExportFormat::Csv),
+            "geojson" => Some(ExportFormat::GeoJson),
+            _ => None,
+        }
+    }
+
+    /// The format matching an Accept header, if any
+    pub(crate) fn from_accept(accept: &str) -> Option<Self> {
+        if accept.contains("text/csv") {
+            Some(ExportFormat::Csv)
+        } else if accept.contains("application/geo+json") {
+            Some(ExportFormat::GeoJson)
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::GeoJson => "application/geo+json",
+        }
+    }
+
+    pub(crate) fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::GeoJson => "geojson",
+        }
+    }
+}
+
+/// Export the entries with their last modifying user, empty for entries without attribution
+pub(crate) fn export(
+    sync_data_list: &HashMap<String, SyncData>,
+    attribution: &HashMap<String, Attribution>,
+    format: ExportFormat,
+) -> String {
+    match format {
+        ExportFormat::Csv => to_csv(sync_data_list, attribution),
+        ExportFormat::GeoJson => to_geojson(sync_data_list, attribution).to_string(),
+    }
+}
+
+/// One line per entry, sorted by id
+fn to_csv(
+    sync_data_list: &HashMap<String, SyncData>,
+    attribution: &HashMap<String, Attribution>,
+) -> String {
+    let mut csv =
+        String::from("id,status,server_update_time,last_modified_by,last_modified_team\n");
+    for (id, sync_data) in sorted(sync_data_list) {
+        let (username, team) = attribution.get(id).map_or(("", ""), |attribution| {
+            (attribution.username.as_str(), attribution.team.as_str())
+        });
+        csv.push_str(&format!(
+            "{},{},{},{},{}\n",
+            csv_field(id),
+            csv_field(&sync_data.status.to_string()),
+            sync_data.server_update_time,
+            csv_field(username),
+            csv_field(team)
+        ));
+    }
+    csv
+}
+
+/// Quote the field if needed. A field a spreadsheet would run as a formula is prefixed with '
+fn csv_field(value: &str) -> String {
+    if value.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        format!("\"'{}\"", value.replace('"', "\"\""))
+    } else if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// A feature collection with one feature per entry.
+/// The sync data do not carry the roadwork location so the geometry is null,
+/// GIS tools join the features with the opendata feed by id
+fn to_geojson(
+    sync_data_list: &HashMap<String, SyncData>,
+    attribution: &HashMap<String, Attribution>,
+) -> Value {
+    let features: Vec<Value> = sorted(sync_data_list)
+        .into_iter()
+        .map(|(id, sync_data)| {
+            let attribution = attribution.get(id);
+            json!({
+                "type": "Feature",
+                "id": id,
+                "geometry": null,
+                "properties": {
+                    "status": sync_data.status.to_string(),
+                    "server_update_time": sync_data.server_update_time,
+                    "last_modified_by": attribution.map(|attribution| &attribution.username),
+                    "last_modified_team": attribution.map(|attribution| &attribution.team),
+                }
+            })
+        })
+        .collect();
+    json!({
+        "type": "FeatureCollection",
+        "features": features,
+    })
+}
+
+fn sorted(sync_data_list: &HashMap<String, SyncData>) -> Vec<(&String, &SyncData)> {
+    let mut entries: Vec<(&String, &SyncData)> = sync_data_list.iter().collect();
+    entries.sort_by_key(|(id, _)| *id);
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_field_quotes() {
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+
+    #[test]
+    fn csv_field_neutralizes_formulas() {
+        assert_eq!(csv_field("=1+1"), "\"'=1+1\"");
+        assert_eq!(csv_field("+33"), "\"'+33\"");
+        assert_eq!(csv_field("-2"), "\"'-2\"");
+        assert_eq!(csv_field("@SUM(A1)"), "\"'@SUM(A1)\"");
+        assert_eq!(csv_field("\tcmd"), "\"'\tcmd\"");
+        assert_eq!(csv_field("\rcmd"), "\"'\rcmd\"");
+        assert_eq!(
+            csv_field("=HYPERLINK(\"http://x\")"),
+            "\"'=HYPERLINK(\"\"http://x\"\")\""
+        );
+    }
+}
@@ -0,0 +1,369 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use log::{info, warn};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::config::Config;
+use crate::service::data::DataService;
+use crate::service::data::opendata_service::is_valid_opendata_service_name;
+use crate::service::data_repository::{DataRepository, OpendataFeed};
+use crate::service::user_repository::UserRepository;
+
+/// How long a feed may take to download
+const FEED_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The result of pulling a feed
+#[derive(Debug, Default, Serialize)]
+pub(crate) struct FeedReport {
+    pub(crate) service: String,
+    /// The roadworks found in the feed
+    pub(crate) items: usize,
+    /// The roadworks added, by team
+    pub(crate) added: HashMap<String, usize>,
+    /// Why the feed could not be merged into a team dataset, by team
+    pub(crate) errors: HashMap<String, String>,
+}
+
+#[derive(Clone)]
+pub(crate) struct FeedService {
+    data_repository: DataRepository,
+    data_service: DataService,
+    user_repository: UserRepository,
+    client: reqwest::Client,
+    config: Config,
+}
+
+impl FeedService {
+    pub(crate) fn new(
+        data_repository: DataRepository,
+        data_service: DataService,
+        user_repository: UserRepository,
+        config: Config,
+    ) -> Result<Self, reqwest::Error> {
+        let client = reqwest::Client::builder().timeout(FEED_TIMEOUT).build()?;
+        Ok(FeedService {
+            data_repository,
+            data_service,
+            user_repository,
+            client,
+            config,
+        })
+    }
+
+    pub(crate) async fn list_feeds(&self) -> Result<Vec<OpendataFeed>, String> {
+        self.data_repository.list_feeds().await
+    }
+
+    /// Insert or replace the feed of an opendata service of the catalog
+    pub(crate) async fn save_feed(&self, feed: &OpendataFeed) -> Result<(), String> {
+        if !is_valid_opendata_service_name(&feed.service) {
+            return Err(format!("Malformed opendata service name {}", feed.service));
+        }
+        if self
+            .data_service
+            .find_opendata_service(&feed.service)
+            .await?
+            .is_none()
+        {
+            return Err(format!("Unknown opendata service {}", feed.service));
+        }
+        if feed.url.is_empty() || feed.id_field.is_empty() {
+            return Err(format!("Missing url or id field for {}", feed.service));
+        }
+        if !is_http_url(&feed.url) {
+            self.local_path(&feed.url).await?;
+        }
+        let teams = self.user_repository.list_teams().await;
+        if let Some(team) = feed.teams.iter().find(|team| !teams.contains(team)) {
+            return Err(format!("Unknown team {}", team));
+        }
+        self.data_repository.save_feed(feed).await
+    }
+
+    pub(crate) async fn delete_feed(&self, service: &str) -> Result<(), String> {
+        self.data_repository.delete_feed(service).await
+    }
+
+    /// Pull the feed of an opendata service now, None if the service has no feed
+    pub(crate) async fn run_feed(&self, service: &str) -> Result<Option<FeedReport>, String> {
+        let Some(feed) = self.data_repository.find_feed(service).await? else {
+            return Ok(None);
+        };
+        self.pull(&feed).await.map(Some)
+    }
+
+    /// Pull every feed, a failing feed does not prevent the others from being pulled
+    async fn run_feeds(&self) -> Result<(), String> {
+        for feed in self.data_repository.list_feeds().await? {
+            match self.pull(&feed).await {
+                Ok(report) => info!(
+                    "feed {} items={} added={:?} errors={:?}",
+                    report.service, report.items, report.added, report.errors
+                ),
+                Err(err) => warn!("feed {} failed: {}", feed.service, err),
+            }
+        }
+        Ok(())
+    }
+
+    /// Download a feed and add its new roadworks to the datasets of the subscribed teams
+    async fn pull(&self, feed: &OpendataFeed) -> Result<FeedReport, String> {
+        info!("pull feed {} from {}", feed.service, feed.url);
+        let document = self.fetch(&feed.url).await?;
+        let ids = extract_ids(feed, &document)?;
+        let mut report = FeedReport {
+            service: feed.service.clone(),
+            items: ids.len(),
+            ..FeedReport::default()
+        };
+        for team in &feed.teams {
+            match self.data_service.ingest(team, &feed.service, &ids).await {
+                Ok(count) => {
+                    report.added.insert(team.clone(), count);
+                }
+                Err(err) => {
+                    report.errors.insert(team.clone(), err.to_string());
+                }
+            }
+        }
+        Ok(report)
+    }
+
+    /// Read a feed document from an http(s) url or a file of the feeds directory
+    async fn fetch(&self, url: &str) -> Result<Value, String> {
+        let content = if is_http_url(url) {
+            self.client
+                .get(url)
+                .send()
+                .await
+                .and_then(|response| response.error_for_status())
+                .map_err(|err| format!("Error downloading {}: {}", url, err))?
+                .text()
+                .await
+                .map_err(|err| format!("Error downloading {}: {}", url, err))?
+        } else {
+            let path = self.local_path(url).await?;
+            tokio::fs::read_to_string(&path)
+                .await
+                .map_err(|err| format!("Error reading {}: {}", path.display(), err))?
+        };
+        serde_json::from_str(&content).map_err(|err| format!("Error parsing {}: {}", url, err))
+    }
+
+    /// The path of a local feed, relative to the feeds directory.
+    /// The path is resolved so neither .. nor a symbolic link can leave the feeds directory
+    async fn local_path(&self, url: &str) -> Result<PathBuf, String> {
+        let feed_dir = tokio::fs::canonicalize(&self.config.feed_dir)
+            .await
+            .map_err(|err| format!("Error reading {}: {}", self.config.feed_dir, err))?;
+        let path = url.strip_prefix("file://").unwrap_or(url);
+        let path = tokio::fs::canonicalize(feed_dir.join(path))
+            .await
+            .map_err(|err| format!("Error reading {}: {}", path, err))?;
+        if !path.starts_with(&feed_dir) {
+            return Err(format!(
+                "Feed {} is outside of {}",
+                url, self.config.feed_dir
+            ));
+        }
+        Ok(path)
+    }
+}
+
+/// Pull the feeds periodically, the first pull happens at startup
+pub(crate) fn start(feed_service: FeedService, config: Config) {
+    if config.feed_interval_minutes == 0 {
+        info!("feed ingestion is disabled");
+        return;
+    }
+    tokio::spawn(async move {
+        let mut interval =
+            tokio::time::interval(Duration::from_secs(config.feed_interval_minutes * 60));
+        loop {
+            interval.tick().await;
+            if let Err(err) = feed_service.run_feeds().await {
+                warn!("feed ingestion failed: {}", err);
+            }
+        }
+    });
+}
+
+fn is_http_url(url: &str) -> bool {
+    url.starts_with("http://") || url.starts_with("https://")
+}
+
+/// The roadwork ids of a feed document, the items without id are skipped
+fn extract_ids(feed: &OpendataFeed, document: &Value) -> Result<Vec<String>, String> {
+    let items = lookup(document, &feed.items_path)
+        .and_then(Value::as_array)
+        .ok_or_else(|| {
+            format!(
+                "No roadwork array at '{}' in feed {}",
+                feed.items_path, feed.service
+            )
+        })?;
+    let ids: Vec<String> = items
+        .iter()
+        .filter_map(|item| lookup(item, &feed.id_field).and_then(id_string))
+        .collect();
+    if ids.len() < items.len() {
+        warn!(
+            "feed {}: {} items without {}",
+            feed.service,
+            items.len() - ids.len(),
+            feed.id_field
+        );
+    }
+    Ok(ids)
+}
+
+/// Follow a dotted path, an empty path is the value itself
+fn lookup<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.')
+        .filter(|key| !key.is_empty())
+        .try_fold(value, |value, key| value.get(key))
+}
+
+fn id_string(value: &Value) -> Option<String> {
+    match value {
+        Value::String(id) if !id.is_empty() => Some(id.clone()),
+        Value::Number(id) => Some(id.to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::service::data_repository::OpendataService;
+    use crate::service::test_http;
+
+    async fn feed_service(feed_dir: &str) -> (FeedService, DataService) {
+        let mut config = Config::for_tests();
+        config.feed_dir = feed_dir.to_string();
+        let user_repository = UserRepository::new(&config).await.unwrap();
+        user_repository.insert_team("team").await.unwrap();
+        let data_repository = DataRepository::new(user_repository.pool(), &config)
+            .await
+            .unwrap();
+        let (webhook_changes, _) = mpsc::unbounded_channel();
+        let data_service =
+            DataService::new(data_repository.clone(), config.clone(), webhook_changes);
+        data_service
+            .save_opendata_service(&OpendataService {
+                id: "service".to_string(),
+                label: "Service".to_string(),
+                description: String::new(),
+                status_meaning: String::new(),
+            })
+            .await
+            .unwrap();
+        let feed_service = FeedService::new(
+            data_repository,
+            data_service.clone(),
+            user_repository,
+            config,
+        )
+        .unwrap();
+        (feed_service, data_service)
+    }
+
+    fn feed(url: &str, teams: &[&str]) -> OpendataFeed {
+        OpendataFeed {
+            service: "service".to_string(),
+            url: url.to_string(),
+            items_path: "records".to_string(),
+            id_field: "fields.id".to_string(),
+            teams: teams.iter().map(|team| team.to_string()).collect(),
+        }
+    }
+
+    #[tokio::test]
+    async fn pull_http_feed() {
+        let document =
+            r#"{"records": [{"fields": {"id": "a"}}, {"fields": {"id": 2}}, {"fields": {}}]}"#;
+        let (addr, mut requests) = test_http::serve(vec![(200, document.to_string())]).await;
+        let (feed_service, data_service) = feed_service("feeds").await;
+        feed_service
+            .save_feed(&feed(&format!("http://{}/feed.json", addr), &["team"]))
+            .await
+            .unwrap();
+
+        let report = feed_service.run_feed("service").await.unwrap().unwrap();
+        assert_eq!(requests.recv().await.unwrap().path, "/feed.json");
+        assert_eq!(report.items, 2);
+        assert_eq!(report.added["team"], 2);
+        let sync_data_list = data_service.get_data("team", "service").await.unwrap();
+        assert!(sync_data_list.contains_key("a") && sync_data_list.contains_key("2"));
+        let history = data_service
+            .get_history("team", "service", "a")
+            .await
+            .unwrap();
+        assert_eq!(history[0].username, "system:ingestion");
+
+        // the known roadworks are not added again
+        let report = feed_service.run_feed("service").await.unwrap().unwrap();
+        assert_eq!(report.added["team"], 0);
+    }
+
+    #[tokio::test]
+    async fn refuse_unknown_team() {
+        let (feed_service, _) = feed_service("feeds").await;
+        assert!(
+            feed_service
+                .save_feed(&feed("http://localhost/feed.json", &["team", "unknown"]))
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn read_local_feeds_from_feed_dir_only() {
+        let test_dir =
+            std::env::temp_dir().join(format!("roadwork-test-{}", rand::random::<u64>()));
+        let feed_dir = test_dir.join("feeds");
+        std::fs::create_dir_all(&feed_dir).unwrap();
+        let document = r#"{"records": [{"fields": {"id": "x"}}]}"#;
+        std::fs::write(feed_dir.join("feed.json"), document).unwrap();
+        std::fs::write(test_dir.join("outside.json"), document).unwrap();
+        let (feed_service, _) = feed_service(&feed_dir.to_string_lossy()).await;
+
+        for url in [
+            "feed.json".to_string(),
+            format!("file://{}", feed_dir.join("feed.json").display()),
+        ] {
+            feed_service
+                .save_feed(&feed(&url, &["team"]))
+                .await
+                .unwrap();
+            assert_eq!(
+                feed_service
+                    .run_feed("service")
+                    .await
+                    .unwrap()
+                    .unwrap()
+                    .items,
+                1
+            );
+        }
+        for url in [
+            "../outside.json".to_string(),
+            format!("file://{}", test_dir.join("outside.json").display()),
+            "/etc/passwd".to_string(),
+        ] {
+            assert!(
+                feed_service
+                    .save_feed(&feed(&url, &["team"]))
+                    .await
+                    .is_err()
+            );
+            assert!(feed_service.fetch(&url).await.is_err());
+        }
+        std::fs::remove_dir_all(test_dir).unwrap();
+    }
+}
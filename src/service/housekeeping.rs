@@ -0,0 +1,27 @@
+use std::time::Duration;
+
+use log::{info, warn};
+
+use crate::config::Config;
+use crate::service::data::{DAY_MILLIS, DataService};
+
+const HOUSEKEEPING_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Start the background task archiving the entries older than their retention and purging the expired tombstones
+pub(crate) fn start(data_service: DataService, config: Config) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(HOUSEKEEPING_INTERVAL);
+        loop {
+            interval.tick().await;
+            match data_service.purge_stale_entries().await {
+                Ok(count) => info!("housekeeping archived {} stale entries", count),
+                Err(err) => warn!("housekeeping failed: {}", err),
+            }
+            let retention = config.tombstone_retention_days.saturating_mul(DAY_MILLIS);
+            match data_service.purge_tombstones(retention).await {
+                Ok(count) => info!("housekeeping purged {} tombstones", count),
+                Err(err) => warn!("housekeeping failed: {}", err),
+            }
+        }
+    });
+}
@@ -1,3 +1,14 @@
 pub(crate) mod data;
+pub(crate) mod data_repository;
+pub(crate) mod data_store;
+pub(crate) mod export;
+pub(crate) mod feed;
+pub(crate) mod housekeeping;
+pub(crate) mod throttle;
 pub(crate) mod user;
 pub(crate) mod user_repository;
+pub(crate) mod webhook;
+
+/// A local HTTP server standing in for the webhooks and the opendata feeds
+#[cfg(test)]
+pub(crate) mod test_http;
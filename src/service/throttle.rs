@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use log::{info, warn};
+use serde::Serialize;
+
+use crate::config::Config;
+
+/// What the failed password attempts are counted for
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+enum ThrottleKey {
+    User(String),
+    Client(IpAddr),
+}
+
+struct Failures {
+    count: u32,
+    last_failure: Instant,
+    blocked_until: Instant,
+}
+
+/// A username or a client address that must wait before trying a password again
+#[derive(Debug, Serialize)]
+pub(crate) struct Lockout {
+    pub(crate) username: Option<String>,
+    pub(crate) client: Option<IpAddr>,
+    pub(crate) failures: u32,
+    /// How long before the next attempt is accepted, in seconds
+    pub(crate) retry_after: u64,
+    /// True once the threshold is reached, false during the backoff of a username
+    pub(crate) locked: bool,
+}
+
+/// Slows down the password guessing: each failed attempt makes the username wait twice as long
+/// as the previous one, until it is locked out for a while. A client address is only locked out,
+/// at a higher threshold, so the users behind a shared address are not slowed down by each other.
+/// The failures are kept in memory, a restart unlocks everything
+#[derive(Clone)]
+pub(crate) struct LoginThrottle {
+    failures: Arc<Mutex<HashMap<ThrottleKey, Failures>>>,
+    config: Config,
+}
+
+impl LoginThrottle {
+    pub(crate) fn new(config: Config) -> Self {
+        LoginThrottle {
+            failures: Arc::new(Mutex::new(HashMap::new())),
+            config,
+        }
+    }
+
+    /// How long the username or the client must wait before trying a password, None if they can try now
+    pub(crate) fn retry_after(&self, username: &str, client: Option<IpAddr>) -> Option<Duration> {
+        let failures = self.failures.lock().unwrap();
+        let now = Instant::now();
+        keys(username, client)
+            .iter()
+            .filter_map(|key| failures.get(key))
+            .filter(|failures| failures.blocked_until > now)
+            .map(|failures| failures.blocked_until - now)
+            .max()
+    }
+
+    pub(crate) fn record_failure(&self, username: &str, client: Option<IpAddr>) {
+        let mut failures = self.failures.lock().unwrap();
+        let now = Instant::now();
+        let lockout_duration = self.lockout_duration();
+        // the failures older than a lockout are forgotten
+        failures.retain(|_, failures| now - failures.last_failure < lockout_duration);
+        for key in keys(username, client) {
+            let threshold = match key {
+                ThrottleKey::User(_) => self.config.lockout_threshold,
+                ThrottleKey::Client(_) => self.config.client_lockout_threshold,
+            };
+            let entry = failures.entry(key.clone()).or_insert(Failures {
+                count: 0,
+                last_failure: now,
+                blocked_until: now,
+            });
+            entry.count += 1;
+            entry.last_failure = now;
+            if entry.count >= threshold {
+                warn!("{:?} is locked out after {} failures", key, entry.count);
+                entry.blocked_until = now + lockout_duration;
+            } else if let ThrottleKey::User(_) = key {
+                entry.blocked_until = now
+                    + backoff(self.config.login_backoff_seconds, entry.count).min(lockout_duration);
+            }
+        }
+    }
+
+    /// A successful login forgets the failures of the username, not the ones of the client
+    pub(crate) fn record_success(&self, username: &str) {
+        self.failures
+            .lock()
+            .unwrap()
+            .remove(&ThrottleKey::User(username.to_string()));
+    }
+
+    /// The usernames and client addresses currently waiting
+    pub(crate) fn list_lockouts(&self) -> Vec<Lockout> {
+        let failures = self.failures.lock().unwrap();
+        let now = Instant::now();
+        let mut lockouts: Vec<Lockout> = failures
+            .iter()
+            .filter(|(_, failures)| failures.blocked_until > now)
+            .map(|(key, failures)| {
+                let (username, client, threshold) = match key {
+                    ThrottleKey::User(username) => {
+                        (Some(username.clone()), None, self.config.lockout_threshold)
+                    }
+                    ThrottleKey::Client(client) => {
+                        (None, Some(*client), self.config.client_lockout_threshold)
+                    }
+                };
+                Lockout {
+                    username,
+                    client,
+                    failures: failures.count,
+                    retry_after: (failures.blocked_until - now).as_secs() + 1,
+                    locked: failures.count >= threshold,
+                }
+            })
+            .collect();
+        lockouts.sort_by_key(|lockout| std::cmp::Reverse(lockout.retry_after));
+        lockouts
+    }
+
+    /// Forget the failures of a username, returns false if it had none
+    pub(crate) fn unlock_user(&self, username: &str) -> bool {
+        info!("unlock_user {}", username);
+        self.failures
+            .lock()
+            .unwrap()
+            .remove(&ThrottleKey::User(username.to_string()))
+            .is_some()
+    }
+
+    /// Forget the failures of a client address, returns false if it had none
+    pub(crate) fn unlock_client(&self, client: IpAddr) -> bool {
+        info!("unlock_client {}", client);
+        self.failures
+            .lock()
+            .unwrap()
+            .remove(&ThrottleKey::Client(client))
+            .is_some()
+    }
+
+    fn lockout_duration(&self) -> Duration {
+        Duration::from_secs(self.config.lockout_minutes * 60)
+    }
+}
+
+fn keys(username: &str, client: Option<IpAddr>) -> Vec<ThrottleKey> {
+    let mut keys = vec![ThrottleKey::User(username.to_string())];
+    if let Some(client) = client {
+        keys.push(ThrottleKey::Client(client));
+    }
+    keys
+}
+
+/// The delay after the given number of failures, doubled after each failure
+fn backoff(backoff_seconds: u64, count: u32) -> Duration {
+    Duration::from_secs(backoff_seconds.saturating_mul(1 << (count - 1).min(30)))
+}
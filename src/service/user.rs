@@ -1,16 +1,249 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::net::IpAddr;
+use std::time::Duration;
+
+use crate::config::Config;
 use crate::hash;
-use crate::service::user_repository::UserRepository;
+use crate::hash::HashAlgorithm;
+use crate::service::data::now;
+use crate::service::data::opendata_service::is_valid_opendata_service_name;
+use crate::service::throttle::{Lockout, LoginThrottle};
+use crate::service::user_repository::{ApiKey, UserRepository};
 use log::{debug, info, warn};
 use roadwork_sync_lib::user::User;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+/// The prefix telling the api keys from the access tokens
+pub(crate) const API_KEY_PREFIX: &str = "rwk_";
+
+/// The prefix of the names the server records in the history for its own changes
+const SYSTEM_USER_PREFIX: &str = "system:";
+
+/// The prefix of the names the changes made with an api key are recorded under
+const API_KEY_USER_PREFIX: &str = "key:";
+
+/// What a client authenticates with: its password, an access token returned by the login,
+/// or an api key of a team. The address of the client is kept with the password to throttle the guesses
+pub(crate) enum Credentials {
+    Basic(String, Option<String>, Option<IpAddr>),
+    Bearer(String),
+    ApiKey(String),
+}
+
+impl Display for Credentials {
+    /// The username, the token is never written to the logs
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Credentials::Basic(username, ..) => write!(f, "{}", username),
+            Credentials::Bearer(_) => write!(f, "<bearer token>"),
+            Credentials::ApiKey(_) => write!(f, "<api key>"),
+        }
+    }
+}
+
+pub(crate) enum Access {
+    Read,
+    Write,
+}
+
+/// A caller allowed on the roadwork routes of a team: a member of the team or one of its api keys
+pub(crate) struct TeamMember {
+    /// The name recorded in the history, key:{name} for an api key
+    pub(crate) username: String,
+    api_key: Option<ApiKey>,
+}
+
+impl TeamMember {
+    /// The members can access every opendata service, the api keys only the ones they are scoped to
+    pub(crate) fn can_access(&self, opendata_service: &str, access: Access) -> bool {
+        match &self.api_key {
+            None => true,
+            Some(api_key) => {
+                api_key.accepts(opendata_service)
+                    && !(api_key.read_only && matches!(access, Access::Write))
+            }
+        }
+    }
+}
+
+/// A newly issued api key, the key itself is only returned once
+#[derive(Debug, Serialize)]
+pub(crate) struct IssuedApiKey {
+    #[serde(flatten)]
+    pub(crate) api_key: ApiKey,
+    pub(crate) key: String,
+}
+
+/// The tokens of a login session
+#[derive(Debug, Serialize)]
+pub(crate) struct Token {
+    pub(crate) access_token: String,
+    pub(crate) token_type: &'static str,
+    /// How long the access token is accepted, in seconds
+    pub(crate) expires_in: u64,
+    /// Exchanged once for a new session when the access token expires
+    pub(crate) refresh_token: String,
+}
 
 #[derive(Clone)]
 pub(crate) struct AdminService {
     user_repository: UserRepository,
+    throttle: LoginThrottle,
+    config: Config,
 }
 
 impl AdminService {
-    pub(crate) async fn new(user_repository: UserRepository) -> Self {
-        AdminService { user_repository }
+    pub(crate) async fn new(user_repository: UserRepository, config: Config) -> Self {
+        AdminService {
+            user_repository,
+            throttle: LoginThrottle::new(config.clone()),
+            config,
+        }
+    }
+
+    /// How long the username or the client must wait before trying a password, None if they can try now
+    pub(crate) fn retry_after(&self, username: &str, client: Option<IpAddr>) -> Option<Duration> {
+        self.throttle.retry_after(username, client)
+    }
+
+    pub(crate) fn list_lockouts(&self) -> Vec<Lockout> {
+        self.throttle.list_lockouts()
+    }
+
+    pub(crate) fn unlock_user(&self, username: &str) -> bool {
+        self.throttle.unlock_user(username)
+    }
+
+    pub(crate) fn unlock_client(&self, client: IpAddr) -> bool {
+        self.throttle.unlock_client(client)
+    }
+
+    /// The user of the credentials, None if they are invalid.
+    /// Only the basic credentials need a password check, the tokens are looked up.
+    /// The api keys have no user
+    pub(crate) async fn authenticate(&self, credentials: &Credentials) -> Option<User> {
+        match credentials {
+            Credentials::Basic(username, password, client) => {
+                let password = password.clone().unwrap_or_default();
+                let user = self.get_user(username, &password).await;
+                match &user {
+                    Some(user) => {
+                        self.throttle.record_success(username);
+                        self.rehash_password(user, &password).await;
+                    }
+                    None => self.throttle.record_failure(username, *client),
+                }
+                user
+            }
+            Credentials::Bearer(access_token) => {
+                let username = self
+                    .user_repository
+                    .find_token_user(&hash_token(access_token), now())
+                    .await
+                    .unwrap_or_else(|err| {
+                        warn!("{}", err);
+                        None
+                    });
+                match username {
+                    Some(username) => self.user_repository.find_user(username).await,
+                    None => {
+                        warn!("authenticate token is invalid or expired");
+                        None
+                    }
+                }
+            }
+            Credentials::ApiKey(_) => None,
+        }
+    }
+
+    /// Exchange a username and password for a new session, None if they are invalid
+    pub(crate) async fn login(&self, credentials: &Credentials) -> Option<Token> {
+        info!("login username={}", credentials);
+        let Credentials::Basic(_, Some(password), _) = credentials else {
+            warn!("login needs a username and a password");
+            return None;
+        };
+        let user = self.authenticate(credentials).await?;
+        if user.admin && password == "admin" {
+            warn!("Password was not modified!");
+            return None;
+        }
+        self.new_token(&user.username).await
+    }
+
+    /// Exchange a refresh token for a new session, the old session is revoked
+    pub(crate) async fn refresh(&self, refresh_token: &str) -> Option<Token> {
+        let username = self
+            .user_repository
+            .take_refresh_token(&hash_token(refresh_token), now())
+            .await
+            .unwrap_or_else(|err| {
+                warn!("{}", err);
+                None
+            });
+        match username {
+            Some(username) => {
+                info!("refresh username={}", username);
+                self.new_token(&username).await
+            }
+            None => {
+                warn!("refresh token is invalid or expired");
+                None
+            }
+        }
+    }
+
+    /// Revoke the session of an access token, or every session of the user with basic credentials.
+    /// Returns false if the credentials are invalid
+    pub(crate) async fn logout(&self, credentials: &Credentials) -> Result<bool, String> {
+        match credentials {
+            Credentials::Basic(..) => match self.authenticate(credentials).await {
+                Some(user) => {
+                    info!("logout username={}", user.username);
+                    self.user_repository
+                        .delete_user_tokens(&user.username)
+                        .await
+                        .map(|_| true)
+                }
+                None => Ok(false),
+            },
+            Credentials::Bearer(access_token) => {
+                self.user_repository
+                    .delete_token(&hash_token(access_token))
+                    .await
+            }
+            Credentials::ApiKey(_) => Ok(false),
+        }
+    }
+
+    async fn new_token(&self, username: &str) -> Option<Token> {
+        let now = now();
+        if let Err(err) = self.user_repository.delete_expired_tokens(now).await {
+            warn!("{}", err);
+        }
+        let access_token = random_token();
+        let refresh_token = random_token();
+        let expires_in = self.config.token_ttl_minutes * 60;
+        let refresh_expires_in = self.config.refresh_token_ttl_days * 24 * 60 * 60;
+        self.user_repository
+            .insert_token(
+                username,
+                &hash_token(&access_token),
+                now + expires_in * 1000,
+                &hash_token(&refresh_token),
+                now + refresh_expires_in * 1000,
+            )
+            .await
+            .map_err(|err| warn!("{}", err))
+            .ok()?;
+        Some(Token {
+            access_token,
+            token_type: "Bearer",
+            expires_in,
+            refresh_token,
+        })
     }
 
     /// Retrieve a user from the repository and check it's password
@@ -38,57 +271,152 @@ impl AdminService {
     ) -> Result<(), String> {
         let user_name = user_name.as_ref();
         info!("change_password username={}", user_name);
-        let salted_password = hash::salt(clear_password);
+        let salted_password = self.hash_password(clear_password);
         self.user_repository
             .update_password(user_name, salted_password)
-            .await
+            .await?;
+        // the sessions opened with the old password are closed
+        self.user_repository.delete_user_tokens(user_name).await
     }
 
-    /// Check if the user is an admin
+    /// The user of the credentials if it is an admin
     /// It is admin is the user and password are valid and the password has been modified
-    pub(crate) async fn is_admin(&self, username: &String, password: &String) -> bool {
-        info!("is_admin username={}", username);
-        if password == "admin" {
-            warn!("Password was not modified!");
-            return false;
-        }
-        if let Some(user) = self.user_repository.find_user(username).await {
-            if self.is_valid(&user, password.as_str()) {
-                return user.admin;
-            }
+    pub(crate) async fn find_admin(&self, credentials: &Credentials) -> Option<User> {
+        info!("find_admin username={}", credentials);
+        if has_default_password(credentials) {
+            return None;
         }
-        false
+        self.authenticate(credentials)
+            .await
+            .filter(|user| user.admin)
     }
 
-    pub(crate) async fn has_team(
+    /// The caller if the credentials are valid and belong to a member or an api key of the team
+    pub(crate) async fn team_member(
         &self,
-        username: &String,
-        password: &String,
+        credentials: &Credentials,
         team: &String,
-    ) -> bool {
-        if let Some(user) = self.find_valid_user(username, password).await {
-            if user.teams.contains(team) {
-                return true;
-            }
+    ) -> Option<TeamMember> {
+        if let Credentials::ApiKey(key) = credentials {
+            let api_key = self
+                .user_repository
+                .find_api_key(&hash_token(key))
+                .await
+                .unwrap_or_else(|err| {
+                    warn!("{}", err);
+                    None
+                })
+                .filter(|api_key| &api_key.team == team)?;
+            return Some(TeamMember {
+                username: format!("{}{}", API_KEY_USER_PREFIX, api_key.name),
+                api_key: Some(api_key),
+            });
         }
-        false
+        self.authenticate(credentials)
+            .await
+            .filter(|user| user.teams.contains(team))
+            .map(|user| TeamMember {
+                username: user.username,
+                api_key: None,
+            })
     }
 
-    async fn find_valid_user<S: AsRef<str>>(
+    /// The user of the credentials if it manages the api keys of the team:
+    /// an admin of the server or a member of the team made admin of the team
+    pub(crate) async fn find_team_admin(
         &self,
-        username: &S,
-        password: &String,
+        credentials: &Credentials,
+        team: &String,
     ) -> Option<User> {
-        debug!("find_valid_user -> {}", username.as_ref());
-        if let Some(user) = self.user_repository.find_user(username).await {
-            debug!("find_valid_user : found {:?}", user);
-            if self.is_valid(&user, password) {
-                debug!("find_valid_user password is valid");
-                return Some(user);
-            }
-            debug!("find_valid_user password is invalid");
+        info!("find_team_admin username={} team={}", credentials, team);
+        if has_default_password(credentials) {
+            return None;
+        }
+        let user = self.authenticate(credentials).await?;
+        if user.admin
+            || (user.teams.contains(team)
+                && self
+                    .user_repository
+                    .is_team_admin(&user.username, team)
+                    .await)
+        {
+            Some(user)
+        } else {
+            None
+        }
+    }
+
+    pub(crate) async fn list_api_keys(&self, team: &str) -> Result<Vec<ApiKey>, String> {
+        self.user_repository.list_api_keys(team).await
+    }
+
+    /// Issue a new api key for the team
+    pub(crate) async fn issue_api_key(
+        &self,
+        team: &str,
+        api_key: ApiKey,
+        created_by: &str,
+    ) -> Result<IssuedApiKey, String> {
+        if api_key.name.is_empty() {
+            return Err("Missing api key name".to_string());
+        }
+        if let Some(service) = api_key
+            .services
+            .iter()
+            .find(|service| !is_valid_opendata_service_name(service))
+        {
+            return Err(format!("Malformed opendata service name {}", service));
+        }
+        let key = format!("{}{}", API_KEY_PREFIX, random_token());
+        let mut api_key = ApiKey {
+            team: team.to_string(),
+            created_by: created_by.to_string(),
+            created_time: now(),
+            ..api_key
+        };
+        api_key.id = self
+            .user_repository
+            .insert_api_key(&api_key, &hash_token(&key))
+            .await?;
+        Ok(IssuedApiKey { api_key, key })
+    }
+
+    /// Revoke an api key of the team, returns false if the team has no such key
+    pub(crate) async fn revoke_api_key(&self, team: &str, id: i64) -> Result<bool, String> {
+        self.user_repository.delete_api_key(team, id).await
+    }
+
+    /// Hash a password with the current settings
+    pub(crate) fn hash_password(&self, clear_password: &String) -> String {
+        hash::salt(clear_password, &self.config)
+    }
+
+    /// How many users have a password hash of each algorithm
+    pub(crate) async fn count_password_hashes(
+        &self,
+    ) -> Result<BTreeMap<HashAlgorithm, usize>, String> {
+        let mut counts = BTreeMap::new();
+        for password_hash in self.user_repository.list_password_hashes().await? {
+            *counts.entry(HashAlgorithm::of(&password_hash)).or_insert(0) += 1;
+        }
+        Ok(counts)
+    }
+
+    /// Replace the hash of a user authenticated with a weaker hash than the current settings would produce,
+    /// so the accounts migrate without a password reset
+    async fn rehash_password(&self, user: &User, clear_password: &String) {
+        if !hash::needs_rehash(&user.password_hash, &self.config) {
+            return;
+        }
+        info!("rehash_password username={}", user.username);
+        let salted_password = self.hash_password(clear_password);
+        if let Err(err) = self
+            .user_repository
+            .update_password(&user.username, salted_password)
+            .await
+        {
+            warn!("{}", err);
         }
-        None
     }
 
     fn is_valid<S: AsRef<str>>(&self, user: &User, password: S) -> bool {
@@ -97,3 +425,29 @@ impl AdminService {
         hash::check(&user.password_hash, password)
     }
 }
+
+fn random_token() -> String {
+    hex::encode(rand::random::<[u8; 32]>())
+}
+
+/// The tokens and api keys are stored hashed, they are random enough for a plain SHA-256
+fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// The admin pages are refused until the default password is modified
+fn has_default_password(credentials: &Credentials) -> bool {
+    if let Credentials::Basic(_, Some(password), _) = credentials
+        && password == "admin"
+    {
+        warn!("Password was not modified!");
+        return true;
+    }
+    false
+}
+
+/// The usernames no user can have, so the changes of the server or of an api key
+/// are never attributed to a user
+pub(crate) fn is_reserved_username(username: &str) -> bool {
+    username.starts_with(SYSTEM_USER_PREFIX) || username.starts_with(API_KEY_USER_PREFIX)
+}
@@ -0,0 +1,145 @@
+use log::info;
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::SqliteRow;
+use sqlx::{Executor, Row, Sqlite, Transaction};
+
+use crate::service::user_repository::UserRepository;
+
+/// A key letting an unattended client use the roadwork routes of one team without a user password
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct ApiKey {
+    /// Given by the server when the key is issued
+    #[serde(default)]
+    pub(crate) id: i64,
+    #[serde(default)]
+    pub(crate) team: String,
+    pub(crate) name: String,
+    /// The opendata services the key can access, all of them if empty
+    #[serde(default)]
+    pub(crate) services: Vec<String>,
+    /// A read-only key cannot modify the datasets
+    #[serde(default)]
+    pub(crate) read_only: bool,
+    #[serde(default)]
+    pub(crate) created_by: String,
+    #[serde(default)]
+    pub(crate) created_time: u64,
+}
+
+impl ApiKey {
+    pub(crate) fn accepts(&self, service: &str) -> bool {
+        self.services.is_empty() || self.services.iter().any(|accepted| accepted == service)
+    }
+}
+
+impl UserRepository {
+    /// Create the api key table, it is missing from the databases created before the api keys
+    pub(crate) async fn init_api_key_table(&self) -> Result<(), sqlx::Error> {
+        let query = "
+        CREATE TABLE IF NOT EXISTS api_key (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            team TEXT NOT NULL,
+            name TEXT NOT NULL,
+            key_hash TEXT NOT NULL UNIQUE,
+            services TEXT NOT NULL,
+            read_only BOOLEAN NOT NULL DEFAULT FALSE,
+            created_by TEXT NOT NULL,
+            created_time INTEGER NOT NULL,
+            UNIQUE (team, name),
+            FOREIGN KEY (team) REFERENCES team(name)
+        );
+    ";
+        self.pool.execute(query).await.map(|_| ())
+    }
+
+    pub(crate) async fn list_api_keys(&self, team: &str) -> Result<Vec<ApiKey>, String> {
+        info!("list_api_keys {}", team);
+        let query = "SELECT id, team, name, services, read_only, created_by, created_time FROM api_key WHERE team = ? ORDER BY name";
+        let rows = sqlx::query(query)
+            .bind(team)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|err| format!("Error listing api keys of {}: {}", team, err))?;
+        Ok(rows.iter().map(read_api_key).collect())
+    }
+
+    pub(crate) async fn find_api_key(&self, key_hash: &str) -> Result<Option<ApiKey>, String> {
+        let query = "SELECT id, team, name, services, read_only, created_by, created_time FROM api_key WHERE key_hash = ?";
+        let row = sqlx::query(query)
+            .bind(key_hash)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|err| format!("Error finding api key: {}", err))?;
+        Ok(row.as_ref().map(read_api_key))
+    }
+
+    /// Store an api key, only the hash of the key is kept. Returns its id
+    pub(crate) async fn insert_api_key(
+        &self,
+        api_key: &ApiKey,
+        key_hash: &str,
+    ) -> Result<i64, String> {
+        info!("insert_api_key team={} name={}", api_key.team, api_key.name);
+        let query = "INSERT INTO api_key (team, name, key_hash, services, read_only, created_by, created_time) VALUES (?, ?, ?, ?, ?, ?, ?)";
+        sqlx::query(query)
+            .bind(&api_key.team)
+            .bind(&api_key.name)
+            .bind(key_hash)
+            .bind(api_key.services.join(","))
+            .bind(api_key.read_only)
+            .bind(&api_key.created_by)
+            .bind(api_key.created_time as i64)
+            .execute(&self.pool)
+            .await
+            .map(|result| result.last_insert_rowid())
+            .map_err(|err| {
+                format!(
+                    "Error inserting api key {} of {}: {}",
+                    api_key.name, api_key.team, err
+                )
+            })
+    }
+
+    /// Revoke an api key of a team, returns false if the team has no such key
+    pub(crate) async fn delete_api_key(&self, team: &str, id: i64) -> Result<bool, String> {
+        info!("delete_api_key team={} id={}", team, id);
+        sqlx::query("DELETE FROM api_key WHERE team = ? AND id = ?")
+            .bind(team)
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map(|result| result.rows_affected() > 0)
+            .map_err(|err| format!("Error deleting api key {} of {}: {}", id, team, err))
+    }
+}
+
+/// Delete the api keys of a team, in the transaction deleting the team
+pub(super) async fn delete_team_api_keys(
+    transaction: &mut Transaction<'_, Sqlite>,
+    team: &str,
+) -> Result<(), String> {
+    info!("delete_team_api_keys {}", team);
+    sqlx::query("DELETE FROM api_key WHERE team = ?")
+        .bind(team)
+        .execute(&mut **transaction)
+        .await
+        .map(|_| ())
+        .map_err(|err| format!("Error deleting api keys of {}: {}", team, err))
+}
+
+fn read_api_key(row: &SqliteRow) -> ApiKey {
+    let services: String = row.get(3);
+    ApiKey {
+        id: row.get(0),
+        team: row.get(1),
+        name: row.get(2),
+        services: services
+            .split(',')
+            .filter(|service| !service.is_empty())
+            .map(str::to_string)
+            .collect(),
+        read_only: row.get(4),
+        created_by: row.get(5),
+        created_time: row.get::<i64, _>(6) as u64,
+    }
+}
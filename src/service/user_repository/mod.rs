@@ -1,4 +1,6 @@
+mod api_key;
 mod team;
+mod token;
 mod user;
 
 use std::fs;
@@ -6,9 +8,14 @@ use std::path::Path;
 
 use log::{info, warn};
 use roadwork_sync_lib::user::User;
+use sqlx::sqlite::SqlitePoolOptions;
 use sqlx::{Error, Executor, Pool, Row, Sqlite, SqlitePool};
 
+use crate::config::Config;
 use crate::hash;
+use crate::service::data_store::DataStoreKind;
+
+pub(crate) use api_key::ApiKey;
 
 #[derive(Clone)]
 pub(crate) struct UserRepository {
@@ -16,17 +23,25 @@ pub(crate) struct UserRepository {
 }
 
 impl UserRepository {
-    pub(crate) async fn new() -> Result<Self, sqlx::Error> {
-        let (pool, should_init_db) = get_database_pool().await?;
+    pub(crate) async fn new(config: &Config) -> Result<Self, sqlx::Error> {
+        let (pool, should_init_db) = get_database_pool(config).await?;
         let repository = UserRepository { pool };
 
         if should_init_db {
-            repository.init_db().await;
+            repository.init_db(config).await;
         }
+        repository.init_token_table().await?;
+        repository.init_team_admin_table().await?;
+        repository.init_api_key_table().await?;
         Ok(repository)
     }
 
-    async fn init_db(&self) {
+    /// The database pool, shared with the other repositories
+    pub(crate) fn pool(&self) -> Pool<Sqlite> {
+        self.pool.clone()
+    }
+
+    async fn init_db(&self, config: &Config) {
         let query = "
         CREATE TABLE team (
             name TEXT PRIMARY KEY
@@ -46,24 +61,30 @@ impl UserRepository {
     ";
         self.pool.execute(query).await.unwrap();
         info!("Database initialized");
-        self.init_admin_user().await;
+        self.init_admin_user(config).await;
     }
 
-    async fn init_admin_user(&self) {
+    async fn init_admin_user(&self, config: &Config) {
         info!("init_admin");
         let team = "admin";
         let username = "admin";
         let password = "admin";
-        self.insert_team(team).await;
         let admin_user = User {
             username: username.to_string(),
-            password_hash: hash::salt(&password.to_string()),
+            password_hash: hash::salt(&password.to_string(), config),
             teams: vec![team.to_string()],
             admin: true,
         };
 
-        self.insert_user(&admin_user).await;
-        self.link_user_team(username, team).await;
+        for result in [
+            self.insert_team(team).await,
+            self.insert_user(&admin_user).await,
+            self.link_user_team(username, team).await,
+        ] {
+            if let Err(err) = result {
+                warn!("init_admin failed: {}", err);
+            }
+        }
     }
 
     async fn find_user_teams(&self, username: &str) -> Vec<String> {
@@ -97,7 +118,18 @@ impl UserRepository {
     }
 }
 
-async fn get_database_pool() -> Result<(Pool<Sqlite>, bool), Error> {
+/// With the memory data store the whole database is in memory too, on a single connection
+/// as each SQLite connection would open its own in-memory database
+async fn get_database_pool(config: &Config) -> Result<(Pool<Sqlite>, bool), Error> {
+    if config.data_store == DataStoreKind::Memory {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .connect("sqlite::memory:")
+            .await?;
+        return Ok((pool, true));
+    }
     let db_path = "database/users";
     let should_init_db = !Path::new(db_path).exists();
     if should_init_db {
@@ -1,19 +1,41 @@
-use log::info;
-use sqlx::Row;
+use log::{info, warn};
+use sqlx::{Executor, Row};
 
-use crate::service::user_repository::UserRepository;
+use crate::service::user_repository::{UserRepository, api_key};
 
 impl UserRepository {
+    /// Create the team admin table, it is missing from the databases created before the team admins
+    pub(crate) async fn init_team_admin_table(&self) -> Result<(), sqlx::Error> {
+        let query = "
+        CREATE TABLE IF NOT EXISTS team_admin (
+            username TEXT NOT NULL,
+            team TEXT NOT NULL,
+            PRIMARY KEY (username, team),
+            FOREIGN KEY (username) REFERENCES user(username),
+            FOREIGN KEY (team) REFERENCES team(name)
+        );
+    ";
+        self.pool.execute(query).await.map(|_| ())
+    }
+
     pub(crate) async fn list_teams(&self) -> Vec<String> {
         info!("list_teams");
-        let teams = sqlx::query("SELECT name FROM team ORDER BY name")
+        sqlx::query("SELECT name FROM team ORDER BY name")
             .fetch_all(&self.pool)
             .await
             .unwrap()
             .iter()
             .map(|row| row.get(0))
-            .collect();
-        teams
+            .collect()
+    }
+
+    pub(crate) async fn team_exists(&self, team: &str) -> Result<bool, String> {
+        sqlx::query("SELECT COUNT(*) FROM team WHERE name = ?")
+            .bind(team)
+            .fetch_one(&self.pool)
+            .await
+            .map(|row| row.get::<i64, _>(0) > 0)
+            .map_err(|err| format!("Error finding team {}: {}", team, err))
     }
 
     pub(crate) async fn insert_team(&self, team: &str) -> Result<(), String> {
@@ -31,11 +53,15 @@ impl UserRepository {
         if self.team_has_users(team).await {
             return Err(format!("Team {} has users", team));
         }
-        let query = "DELETE FROM team WHERE name = ?";
-        let result = sqlx::query(query).bind(team).execute(&self.pool).await;
-        result
-            .map(|_| ())
-            .map_err(|err| format!("Error removing team {}: {}", team, err))
+        let to_error = |err: sqlx::Error| format!("Error removing team {}: {}", team, err);
+        let mut transaction = self.pool.begin().await.map_err(to_error)?;
+        api_key::delete_team_api_keys(&mut transaction, team).await?;
+        sqlx::query("DELETE FROM team WHERE name = ?")
+            .bind(team)
+            .execute(&mut *transaction)
+            .await
+            .map_err(to_error)?;
+        transaction.commit().await.map_err(to_error)
     }
 
     async fn team_has_users(&self, team: &String) -> bool {
@@ -48,4 +74,64 @@ impl UserRepository {
             .get::<i64, _>(0);
         count > 0
     }
+
+    /// The members of a team managing its api keys
+    pub(crate) async fn list_team_admins(&self, team: &str) -> Result<Vec<String>, String> {
+        info!("list_team_admins {}", team);
+        let query = "SELECT username FROM team_admin WHERE team = ? ORDER BY username";
+        sqlx::query(query)
+            .bind(team)
+            .fetch_all(&self.pool)
+            .await
+            .map(|rows| rows.iter().map(|row| row.get(0)).collect())
+            .map_err(|err| format!("Error listing admins of team {}: {}", team, err))
+    }
+
+    pub(crate) async fn insert_team_admin(&self, username: &str, team: &str) -> Result<(), String> {
+        info!("insert_team_admin {} {}", username, team);
+        let query = "INSERT OR IGNORE INTO team_admin (username, team) VALUES (?, ?)";
+        sqlx::query(query)
+            .bind(username)
+            .bind(team)
+            .execute(&self.pool)
+            .await
+            .map(|_| ())
+            .map_err(|err| {
+                format!(
+                    "Error making user {} admin of team {}: {}",
+                    username, team, err
+                )
+            })
+    }
+
+    pub(crate) async fn delete_team_admin(&self, username: &str, team: &str) -> Result<(), String> {
+        info!("delete_team_admin {} {}", username, team);
+        let query = "DELETE FROM team_admin WHERE username = ? AND team = ?";
+        sqlx::query(query)
+            .bind(username)
+            .bind(team)
+            .execute(&self.pool)
+            .await
+            .map(|_| ())
+            .map_err(|err| {
+                format!(
+                    "Error removing user {} from the admins of team {}: {}",
+                    username, team, err
+                )
+            })
+    }
+
+    pub(crate) async fn is_team_admin(&self, username: &str, team: &str) -> bool {
+        let query = "SELECT COUNT(*) FROM team_admin WHERE username = ? AND team = ?";
+        sqlx::query(query)
+            .bind(username)
+            .bind(team)
+            .fetch_one(&self.pool)
+            .await
+            .map(|row| row.get::<i64, _>(0) > 0)
+            .unwrap_or_else(|err| {
+                warn!("Error checking admins of team {}: {}", team, err);
+                false
+            })
+    }
 }
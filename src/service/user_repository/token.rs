@@ -0,0 +1,108 @@
+use log::info;
+use sqlx::{Executor, Row};
+
+use crate::service::user_repository::UserRepository;
+
+impl UserRepository {
+    /// Create the token table, it is missing from the databases created before the token login
+    pub(crate) async fn init_token_table(&self) -> Result<(), sqlx::Error> {
+        let query = "
+        CREATE TABLE IF NOT EXISTS user_token (
+            access_hash TEXT PRIMARY KEY,
+            refresh_hash TEXT NOT NULL UNIQUE,
+            username TEXT NOT NULL,
+            access_expiry INTEGER NOT NULL,
+            refresh_expiry INTEGER NOT NULL,
+            FOREIGN KEY (username) REFERENCES user(username)
+        );
+        CREATE INDEX IF NOT EXISTS user_token_username ON user_token (username);
+    ";
+        self.pool.execute(query).await.map(|_| ())
+    }
+
+    /// Store a login session, only the hashes of the tokens are kept
+    pub(crate) async fn insert_token(
+        &self,
+        username: &str,
+        access_hash: &str,
+        access_expiry: u64,
+        refresh_hash: &str,
+        refresh_expiry: u64,
+    ) -> Result<(), String> {
+        info!("insert_token {}", username);
+        let query = "INSERT INTO user_token (access_hash, refresh_hash, username, access_expiry, refresh_expiry) VALUES (?, ?, ?, ?, ?)";
+        sqlx::query(query)
+            .bind(access_hash)
+            .bind(refresh_hash)
+            .bind(username)
+            .bind(access_expiry as i64)
+            .bind(refresh_expiry as i64)
+            .execute(&self.pool)
+            .await
+            .map(|_| ())
+            .map_err(|err| format!("Error inserting token for user {}: {}", username, err))
+    }
+
+    /// The user of an access token that has not expired
+    pub(crate) async fn find_token_user(
+        &self,
+        access_hash: &str,
+        now: u64,
+    ) -> Result<Option<String>, String> {
+        let query = "SELECT username FROM user_token WHERE access_hash = ? AND access_expiry > ?";
+        sqlx::query(query)
+            .bind(access_hash)
+            .bind(now as i64)
+            .fetch_optional(&self.pool)
+            .await
+            .map(|row| row.map(|row| row.get(0)))
+            .map_err(|err| format!("Error finding token: {}", err))
+    }
+
+    /// Remove the session of a refresh token that has not expired and return its user,
+    /// a refresh token can only be used once
+    pub(crate) async fn take_refresh_token(
+        &self,
+        refresh_hash: &str,
+        now: u64,
+    ) -> Result<Option<String>, String> {
+        let query = "DELETE FROM user_token WHERE refresh_hash = ? AND refresh_expiry > ? RETURNING username";
+        sqlx::query(query)
+            .bind(refresh_hash)
+            .bind(now as i64)
+            .fetch_optional(&self.pool)
+            .await
+            .map(|row| row.map(|row| row.get(0)))
+            .map_err(|err| format!("Error taking refresh token: {}", err))
+    }
+
+    /// Revoke the session of an access token
+    pub(crate) async fn delete_token(&self, access_hash: &str) -> Result<bool, String> {
+        sqlx::query("DELETE FROM user_token WHERE access_hash = ?")
+            .bind(access_hash)
+            .execute(&self.pool)
+            .await
+            .map(|result| result.rows_affected() > 0)
+            .map_err(|err| format!("Error deleting token: {}", err))
+    }
+
+    /// Revoke every session of a user
+    pub(crate) async fn delete_user_tokens(&self, username: &str) -> Result<(), String> {
+        info!("delete_user_tokens {}", username);
+        sqlx::query("DELETE FROM user_token WHERE username = ?")
+            .bind(username)
+            .execute(&self.pool)
+            .await
+            .map(|_| ())
+            .map_err(|err| format!("Error deleting tokens of user {}: {}", username, err))
+    }
+
+    pub(crate) async fn delete_expired_tokens(&self, now: u64) -> Result<(), String> {
+        sqlx::query("DELETE FROM user_token WHERE refresh_expiry <= ?")
+            .bind(now as i64)
+            .execute(&self.pool)
+            .await
+            .map(|_| ())
+            .map_err(|err| format!("Error deleting expired tokens: {}", err))
+    }
+}
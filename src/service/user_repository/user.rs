@@ -2,19 +2,28 @@ use log::info;
 use roadwork_sync_lib::user::User;
 use sqlx::Row;
 
+use crate::service::user::is_reserved_username;
 use crate::service::user_repository::UserRepository;
 
 impl UserRepository {
     pub(crate) async fn list_users(&self) -> Vec<String> {
         info!("list_users");
-        let users = sqlx::query("SELECT username FROM user ORDER BY username")
+        sqlx::query("SELECT username FROM user ORDER BY username")
             .fetch_all(&self.pool)
             .await
             .unwrap()
             .iter()
             .map(|row| row.get(0))
-            .collect();
-        users
+            .collect()
+    }
+
+    /// The password hashes of every user
+    pub(crate) async fn list_password_hashes(&self) -> Result<Vec<String>, String> {
+        sqlx::query("SELECT password_hash FROM user")
+            .fetch_all(&self.pool)
+            .await
+            .map(|rows| rows.iter().map(|row| row.get(0)).collect())
+            .map_err(|err| format!("Error listing password hashes: {}", err))
     }
 
     pub(crate) async fn update_password(
@@ -59,6 +68,9 @@ impl UserRepository {
 
     pub(crate) async fn insert_user(&self, user: &User) -> Result<(), String> {
         info!("insert_user {}", user.username);
+        if is_reserved_username(&user.username) {
+            return Err(format!("Username {} is reserved", user.username));
+        }
         let query = "INSERT INTO user (username, password_hash, admin) VALUES (?, ?, ?)";
         let result = sqlx::query(query)
             .bind(&user.username)
@@ -74,6 +86,7 @@ impl UserRepository {
     pub(crate) async fn delete_user(&self, username: &String) -> Result<(), String> {
         info!("delete_user {}", username);
         self.remove_all_user_teams(username).await?;
+        self.delete_user_tokens(username).await?;
         let query = "DELETE FROM user WHERE username = ?";
         let result = sqlx::query(query)
             .bind(username)
@@ -86,6 +99,14 @@ impl UserRepository {
 
     async fn remove_all_user_teams(&self, username: &String) -> Result<(), String> {
         info!("remove_all_user_teams {}", username);
+        let query = "DELETE FROM team_admin WHERE username = ?";
+        let result = sqlx::query(query).bind(username).execute(&self.pool).await;
+        result.map_err(|err| {
+            format!(
+                "Error removing user {} from the team admins : {}",
+                username, err
+            )
+        })?;
         let query = "DELETE FROM user_team WHERE username = ?";
         let result = sqlx::query(query).bind(username).execute(&self.pool).await;
         result
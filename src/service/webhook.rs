@@ -0,0 +1,540 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use hmac::{Hmac, Mac};
+use log::{info, warn};
+use serde_json::json;
+use sha2::Sha256;
+use tokio::sync::mpsc;
+
+use crate::config::Config;
+use crate::service::data::opendata_service::is_valid_opendata_service_name;
+use crate::service::data::{DatasetChange, now};
+use crate::service::data_repository::{DataRepository, StatusChange, Webhook, WebhookDelivery};
+use crate::service::user_repository::UserRepository;
+
+/// How long a webhook may take to answer
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The HMAC-SHA256 of the body with the webhook secret, as sha256={hex}
+const SIGNATURE_HEADER: &str = "X-Roadwork-Signature";
+const EVENT_HEADER: &str = "X-Roadwork-Event";
+
+const STATUS_CHANGE_EVENT: &str = "status_change";
+/// Sent by an admin to check a webhook is reachable
+const PING_EVENT: &str = "ping";
+
+/// How many deliveries of a webhook are listed
+const DELIVERY_LOG_LIMIT: u32 = 100;
+
+/// The longest delay between two delivery attempts
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60 * 60);
+
+/// The events waiting to be delivered with the id of their pending delivery, by webhook id
+type WebhookQueues = Arc<Mutex<HashMap<i64, mpsc::UnboundedSender<(i64, String)>>>>;
+
+#[derive(Clone)]
+pub(crate) struct WebhookService {
+    data_repository: DataRepository,
+    user_repository: UserRepository,
+    client: reqwest::Client,
+    /// Each webhook has its own queue so its events are delivered in order, one at a time
+    queues: WebhookQueues,
+    config: Config,
+}
+
+impl WebhookService {
+    pub(crate) fn new(
+        data_repository: DataRepository,
+        user_repository: UserRepository,
+        config: Config,
+    ) -> Result<Self, reqwest::Error> {
+        let client = reqwest::Client::builder()
+            .timeout(WEBHOOK_TIMEOUT)
+            .build()?;
+        Ok(WebhookService {
+            data_repository,
+            user_repository,
+            client,
+            queues: Arc::new(Mutex::new(HashMap::new())),
+            config,
+        })
+    }
+
+    pub(crate) async fn list_webhooks(&self) -> Result<Vec<Webhook>, String> {
+        self.data_repository.list_webhooks().await
+    }
+
+    /// Register a webhook, returns its id
+    pub(crate) async fn add_webhook(&self, webhook: &Webhook) -> Result<i64, String> {
+        if !webhook.url.starts_with("http://") && !webhook.url.starts_with("https://") {
+            return Err(format!("Invalid webhook url {}", webhook.url));
+        }
+        if let Some(service) = webhook
+            .services
+            .iter()
+            .find(|service| !is_valid_opendata_service_name(service))
+        {
+            return Err(format!("Malformed opendata service name {}", service));
+        }
+        if !self.user_repository.team_exists(&webhook.team).await? {
+            return Err(format!("Unknown team {}", webhook.team));
+        }
+        self.data_repository.insert_webhook(webhook).await
+    }
+
+    /// The events still queued for the webhook are dropped
+    pub(crate) async fn delete_webhook(&self, id: i64) -> Result<(), String> {
+        self.data_repository.delete_webhook(id).await?;
+        self.queues.lock().unwrap().remove(&id);
+        Ok(())
+    }
+
+    /// The latest delivery attempts of a webhook, newest first
+    pub(crate) async fn list_deliveries(&self, id: i64) -> Result<Vec<WebhookDelivery>, String> {
+        self.data_repository
+            .list_webhook_deliveries(id, DELIVERY_LOG_LIMIT)
+            .await
+    }
+
+    /// Send a ping event to a webhook once, None if the webhook does not exist
+    pub(crate) async fn ping(&self, id: i64) -> Result<Option<WebhookDelivery>, String> {
+        info!("ping webhook {}", id);
+        let Some(webhook) = self.data_repository.find_webhook(id).await? else {
+            return Ok(None);
+        };
+        let body = json!({
+            "event": PING_EVENT,
+            "time": now(),
+            "data": null,
+        })
+        .to_string();
+        Ok(Some(self.attempt(&webhook, PING_EVENT, &body, 1).await))
+    }
+
+    /// Send the status changes of a dataset change to the interested webhooks
+    async fn notify(&self, change: &DatasetChange) {
+        if change.status_changes.is_empty() {
+            return;
+        }
+        let webhooks = match self
+            .data_repository
+            .find_webhooks(&change.team, &change.service)
+            .await
+        {
+            Ok(webhooks) => webhooks,
+            Err(err) => {
+                warn!("notify failed: {}", err);
+                return;
+            }
+        };
+        for webhook in webhooks {
+            for status_change in &change.status_changes {
+                let body = status_change_event(status_change);
+                let delivery = WebhookDelivery {
+                    id: 0,
+                    webhook_id: webhook.id,
+                    event: body.clone(),
+                    attempt: 0,
+                    time: now(),
+                    status_code: None,
+                    error: None,
+                    pending: true,
+                };
+                match self
+                    .data_repository
+                    .insert_webhook_delivery(&delivery)
+                    .await
+                {
+                    Ok(delivery_id) => self.enqueue(&webhook, delivery_id, body),
+                    Err(err) => warn!("{}", err),
+                }
+            }
+        }
+    }
+
+    /// Queue again the events still pending when the server stopped
+    async fn resume(&self) {
+        let (webhooks, deliveries) = match (
+            self.data_repository.list_webhooks().await,
+            self.data_repository.list_pending_webhook_deliveries().await,
+        ) {
+            (Ok(webhooks), Ok(deliveries)) => (webhooks, deliveries),
+            (Err(err), _) | (_, Err(err)) => {
+                warn!("resume failed: {}", err);
+                return;
+            }
+        };
+        info!("resume {} webhook deliveries", deliveries.len());
+        let webhooks: HashMap<i64, Webhook> = webhooks
+            .into_iter()
+            .map(|webhook| (webhook.id, webhook))
+            .collect();
+        for delivery in deliveries {
+            if let Some(webhook) = webhooks.get(&delivery.webhook_id) {
+                self.enqueue(webhook, delivery.id, delivery.event);
+            }
+        }
+    }
+
+    /// Queue an event for a webhook, its worker is started with the first event
+    fn enqueue(&self, webhook: &Webhook, delivery_id: i64, body: String) {
+        let mut queues = self.queues.lock().unwrap();
+        let queue = queues
+            .entry(webhook.id)
+            .or_insert_with(|| self.start_worker(webhook.clone()));
+        if queue.send((delivery_id, body)).is_err() {
+            warn!("The worker of webhook {} stopped", webhook.id);
+            queues.remove(&webhook.id);
+        }
+    }
+
+    /// Deliver the queued events of a webhook one after the other, until the webhook is deleted
+    fn start_worker(&self, webhook: Webhook) -> mpsc::UnboundedSender<(i64, String)> {
+        let (queue, mut events) = mpsc::unbounded_channel::<(i64, String)>();
+        let webhook_service = self.clone();
+        tokio::spawn(async move {
+            while let Some((delivery_id, body)) = events.recv().await {
+                if !webhook_service
+                    .queues
+                    .lock()
+                    .unwrap()
+                    .contains_key(&webhook.id)
+                {
+                    break;
+                }
+                let (status_code, error) = webhook_service
+                    .deliver(&webhook, STATUS_CHANGE_EVENT, &body)
+                    .await;
+                if let Err(err) = webhook_service
+                    .data_repository
+                    .finish_webhook_delivery(delivery_id, status_code, &error)
+                    .await
+                {
+                    warn!("{}", err);
+                }
+            }
+        });
+        queue
+    }
+
+    /// Deliver an event, retrying with an exponential backoff until the webhook accepts it.
+    /// Returns the status of the last attempt and why the event was given up
+    async fn deliver(
+        &self,
+        webhook: &Webhook,
+        event: &str,
+        body: &str,
+    ) -> (Option<u16>, Option<String>) {
+        let mut delay = Duration::from_secs(self.config.webhook_retry_delay_seconds);
+        let mut status_code = None;
+        for attempt in 1..=self.config.webhook_max_attempts {
+            let delivery = self.attempt(webhook, event, body, attempt).await;
+            if delivery.error.is_none() {
+                return (delivery.status_code, None);
+            }
+            status_code = delivery.status_code;
+            if attempt < self.config.webhook_max_attempts {
+                tokio::time::sleep(delay).await;
+                delay = delay.saturating_mul(2).min(MAX_RETRY_DELAY);
+            }
+        }
+        warn!(
+            "Giving up delivering {} to webhook {} after {} attempts",
+            event, webhook.id, self.config.webhook_max_attempts
+        );
+        (
+            status_code,
+            Some(format!(
+                "Given up after {} attempts",
+                self.config.webhook_max_attempts
+            )),
+        )
+    }
+
+    /// Post the event once and log the delivery
+    async fn attempt(
+        &self,
+        webhook: &Webhook,
+        event: &str,
+        body: &str,
+        attempt: u32,
+    ) -> WebhookDelivery {
+        info!(
+            "deliver {} to webhook {} attempt {}",
+            event, webhook.id, attempt
+        );
+        let response = self
+            .client
+            .post(&webhook.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(EVENT_HEADER, event)
+            .header(SIGNATURE_HEADER, sign(&webhook.secret, body))
+            .body(body.to_string())
+            .send()
+            .await;
+        let (status_code, error) = match response {
+            Ok(response) if response.status().is_success() => {
+                (Some(response.status().as_u16()), None)
+            }
+            Ok(response) => (
+                Some(response.status().as_u16()),
+                Some(format!("Unexpected status {}", response.status())),
+            ),
+            Err(err) => (None, Some(err.to_string())),
+        };
+        let mut delivery = WebhookDelivery {
+            id: 0,
+            webhook_id: webhook.id,
+            event: body.to_string(),
+            attempt,
+            time: now(),
+            status_code,
+            error,
+            pending: false,
+        };
+        match self
+            .data_repository
+            .insert_webhook_delivery(&delivery)
+            .await
+        {
+            Ok(id) => delivery.id = id,
+            Err(err) => warn!("{}", err),
+        }
+        delivery
+    }
+}
+
+/// Notify the webhooks of the status changes merged into the datasets.
+/// The changes are not lost while the webhooks are slow, they wait in an unbounded channel.
+/// The events pending when the server stopped are delivered first
+pub(crate) fn start(
+    webhook_service: WebhookService,
+    mut changes: mpsc::UnboundedReceiver<DatasetChange>,
+) {
+    tokio::spawn(async move {
+        webhook_service.resume().await;
+        while let Some(change) = changes.recv().await {
+            webhook_service.notify(&change).await;
+        }
+    });
+}
+
+fn status_change_event(status_change: &StatusChange) -> String {
+    json!({
+        "event": STATUS_CHANGE_EVENT,
+        "time": now(),
+        "data": status_change,
+    })
+    .to_string()
+}
+
+fn sign(secret: &str, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(body.as_bytes());
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+    use crate::service::test_http;
+    use crate::service::user_repository::UserRepository;
+
+    fn status_change(id: &str, new_status: &str) -> StatusChange {
+        StatusChange {
+            id: id.to_string(),
+            old_status: None,
+            new_status: new_status.to_string(),
+            time: now(),
+            team: "team".to_string(),
+            service: "service".to_string(),
+            username: "user".to_string(),
+        }
+    }
+
+    async fn webhook_service(max_attempts: u32) -> WebhookService {
+        let mut config = Config::for_tests();
+        config.webhook_max_attempts = max_attempts;
+        config.webhook_retry_delay_seconds = 0;
+        let user_repository = UserRepository::new(&config).await.unwrap();
+        user_repository.insert_team("team").await.unwrap();
+        let data_repository = DataRepository::new(user_repository.pool(), &config)
+            .await
+            .unwrap();
+        WebhookService::new(data_repository, user_repository, config).unwrap()
+    }
+
+    fn webhook(team: &str, url: String) -> Webhook {
+        Webhook {
+            id: 0,
+            team: team.to_string(),
+            url,
+            secret: "secret".to_string(),
+            services: Vec::new(),
+        }
+    }
+
+    /// Wait for the deliveries of a webhook to be finished, oldest first
+    async fn finished_deliveries(
+        webhook_service: &WebhookService,
+        webhook_id: i64,
+        count: usize,
+    ) -> Vec<WebhookDelivery> {
+        let start = Instant::now();
+        loop {
+            let mut deliveries = webhook_service.list_deliveries(webhook_id).await.unwrap();
+            deliveries.reverse();
+            let finished = deliveries
+                .iter()
+                .filter(|delivery| !delivery.pending)
+                .count();
+            if finished == count || start.elapsed() > Duration::from_secs(5) {
+                return deliveries;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    /// The first attempt fails, the retry and the next event are accepted
+    #[tokio::test]
+    async fn deliver_in_order_with_retries() {
+        let webhook_service = webhook_service(3).await;
+        let (addr, mut requests) =
+            test_http::serve(vec![(500, String::new()), (200, String::new())]).await;
+        let webhook_id = webhook_service
+            .add_webhook(&webhook("team", format!("http://{}/hook", addr)))
+            .await
+            .unwrap();
+
+        webhook_service
+            .notify(&DatasetChange {
+                team: "team".to_string(),
+                service: "service".to_string(),
+                username: "user".to_string(),
+                entries: HashMap::new(),
+                deleted: Vec::new(),
+                status_changes: vec![status_change("1", "New"), status_change("2", "Finished")],
+            })
+            .await;
+
+        let mut ids = Vec::new();
+        for _ in 0..3 {
+            let request = requests.recv().await.unwrap();
+            assert_eq!(request.path, "/hook");
+            assert_eq!(request.headers["x-roadwork-event"], STATUS_CHANGE_EVENT);
+            let signature = request.headers["x-roadwork-signature"]
+                .strip_prefix("sha256=")
+                .unwrap();
+            let mut mac = Hmac::<Sha256>::new_from_slice(b"secret").unwrap();
+            mac.update(request.body.as_bytes());
+            mac.verify_slice(&hex::decode(signature).unwrap()).unwrap();
+            let event: serde_json::Value = serde_json::from_str(&request.body).unwrap();
+            ids.push(event["data"]["id"].as_str().unwrap().to_string());
+        }
+        assert_eq!(ids, vec!["1", "1", "2"]);
+
+        let deliveries = finished_deliveries(&webhook_service, webhook_id, 5).await;
+        let queued: Vec<(Option<u16>, bool)> = deliveries
+            .iter()
+            .filter(|delivery| delivery.attempt == 0)
+            .map(|delivery| (delivery.status_code, delivery.error.is_some()))
+            .collect();
+        assert_eq!(queued, vec![(Some(200), false), (Some(200), false)]);
+        let deliveries: Vec<(u32, Option<u16>, bool)> = deliveries
+            .iter()
+            .filter(|delivery| delivery.attempt > 0)
+            .map(|delivery| {
+                (
+                    delivery.attempt,
+                    delivery.status_code,
+                    delivery.error.is_some(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            deliveries,
+            vec![
+                (1, Some(500), true),
+                (2, Some(200), false),
+                (1, Some(200), false)
+            ]
+        );
+    }
+
+    /// The events pending when the server stopped are delivered at startup,
+    /// the events given up are recorded
+    #[tokio::test]
+    async fn resume_pending_deliveries() {
+        let webhook_service = webhook_service(1).await;
+        let (addr, mut requests) =
+            test_http::serve(vec![(200, String::new()), (500, String::new())]).await;
+        let webhook_id = webhook_service
+            .add_webhook(&webhook("team", format!("http://{}/hook", addr)))
+            .await
+            .unwrap();
+        for id in ["1", "2"] {
+            webhook_service
+                .data_repository
+                .insert_webhook_delivery(&WebhookDelivery {
+                    id: 0,
+                    webhook_id,
+                    event: status_change_event(&status_change(id, "New")),
+                    attempt: 0,
+                    time: now(),
+                    status_code: None,
+                    error: None,
+                    pending: true,
+                })
+                .await
+                .unwrap();
+        }
+
+        let (_, changes) = mpsc::unbounded_channel();
+        start(webhook_service.clone(), changes);
+
+        for id in ["1", "2"] {
+            let request = requests.recv().await.unwrap();
+            let event: serde_json::Value = serde_json::from_str(&request.body).unwrap();
+            assert_eq!(event["data"]["id"], id);
+        }
+        let deliveries = finished_deliveries(&webhook_service, webhook_id, 4).await;
+        let queued: Vec<(bool, Option<u16>, Option<String>)> = deliveries
+            .iter()
+            .filter(|delivery| delivery.attempt == 0)
+            .map(|delivery| {
+                (
+                    delivery.pending,
+                    delivery.status_code,
+                    delivery.error.clone(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            queued,
+            vec![
+                (false, Some(200), None),
+                (
+                    false,
+                    Some(500),
+                    Some("Given up after 1 attempts".to_string())
+                )
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn refuse_unknown_team() {
+        let webhook_service = webhook_service(1).await;
+        assert_eq!(
+            webhook_service
+                .add_webhook(&webhook("unknown", "http://localhost/hook".to_string()))
+                .await,
+            Err("Unknown team unknown".to_string())
+        );
+    }
+}